@@ -0,0 +1,9 @@
+use echo_server::crdt::map::CrdtMap;
+use echo_server::crdt::node::Node;
+use echo_server::crdt::pncounter::PNCounter;
+use echo_server::runtime;
+
+// Named PN-Counters, one per key
+fn main() {
+    runtime::run::<Node<CrdtMap<String, PNCounter>>>();
+}
@@ -0,0 +1,6 @@
+use echo_server::echo::node::AsyncNode;
+use echo_server::runtime::async_runtime::run_async;
+
+fn main() {
+    run_async::<AsyncNode>();
+}
@@ -0,0 +1,7 @@
+use echo_server::crdt::gcounter::GCounter;
+use echo_server::crdt::node::Node;
+use echo_server::runtime;
+
+fn main() {
+    runtime::run::<Node<GCounter>>();
+}
@@ -0,0 +1,7 @@
+use echo_server::crdt::lww::LwwRegister;
+use echo_server::crdt::node::Node;
+use echo_server::runtime;
+
+fn main() {
+    runtime::run::<Node<LwwRegister>>();
+}
@@ -0,0 +1,9 @@
+use echo_server::crdt::causal::Causal;
+use echo_server::crdt::node::Node;
+use echo_server::crdt::op_orset::OpORSet;
+use echo_server::runtime;
+
+// Operation-based OR-Set, replicated with causal delivery
+fn main() {
+    runtime::run::<Node<Causal<OpORSet>>>();
+}
@@ -0,0 +1,7 @@
+use echo_server::crdt::node::Node;
+use echo_server::crdt::orset::ORSet;
+use echo_server::runtime;
+
+fn main() {
+    runtime::run::<Node<ORSet>>();
+}
@@ -0,0 +1,239 @@
+// Local checker for the OR-Set, Maelstrom has no workload with removals.
+// Runs a few `or_set` nodes, routes their messages, partitions them while
+// concurrent adds and removes happen, then checks that every node converges
+// to the add-wins outcome.
+//
+// usage: or_set_check [node count] [element count] [binary, or_set by default]
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{self, Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use echo_server::protocol::Message;
+
+const CLIENT: &str = "c1";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const SETTLE: Duration = Duration::from_millis(500);
+
+type Stdins = Arc<HashMap<String, Mutex<ChildStdin>>>;
+
+struct Cluster {
+    nodes: Vec<String>,
+    children: Vec<Child>,
+    stdins: Stdins,
+    replies: Receiver<Message<Value>>,
+    partitioned: Arc<AtomicBool>,
+    next_msg_id: usize,
+}
+
+fn write_line(stdins: &Stdins, dest: &str, line: &str) {
+    if let Some(stdin) = stdins.get(dest) {
+        let mut stdin = stdin.lock().unwrap();
+        let _ = writeln!(stdin, "{}", line);
+        let _ = stdin.flush();
+    }
+}
+
+// Forwards node to node traffic, unless partitioned, and replies to us
+fn route(
+    stdout: impl BufRead,
+    stdins: Stdins,
+    replies: Sender<Message<Value>>,
+    partitioned: Arc<AtomicBool>,
+) {
+    for line in stdout.lines().map_while(Result::ok) {
+        let msg: Message<Value> = match serde_json::from_str(&line) {
+            Ok(msg) => msg,
+            Err(_) => continue,
+        };
+        if msg.dest == CLIENT {
+            let _ = replies.send(msg);
+        } else if !partitioned.load(Ordering::SeqCst) {
+            write_line(&stdins, &msg.dest, &line);
+        }
+    }
+}
+
+impl Cluster {
+    fn start(bin: &str, node_count: usize) -> Self {
+        let nodes: Vec<String> = (0..node_count).map(|i| format!("n{}", i)).collect();
+
+        let mut children = vec![];
+        let mut stdins = HashMap::new();
+        let mut stdouts = vec![];
+        for node in &nodes {
+            let mut child = Command::new(bin)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap_or_else(|e| panic!("Cannot run {}: {}", bin, e));
+            stdins.insert(node.clone(), Mutex::new(child.stdin.take().unwrap()));
+            stdouts.push(BufReader::new(child.stdout.take().unwrap()));
+            children.push(child);
+        }
+
+        let stdins = Arc::new(stdins);
+        let partitioned = Arc::new(AtomicBool::new(false));
+        let (tx, replies) = mpsc::channel();
+        stdouts.into_iter().for_each(|stdout| {
+            let stdins = Arc::clone(&stdins);
+            let tx = tx.clone();
+            let partitioned = Arc::clone(&partitioned);
+            thread::spawn(move || route(stdout, stdins, tx, partitioned));
+        });
+
+        let mut cluster = Cluster {
+            nodes,
+            children,
+            stdins,
+            replies,
+            partitioned,
+            next_msg_id: 0,
+        };
+        let node_ids = cluster.nodes.clone();
+        for node in node_ids.iter() {
+            cluster.call(
+                node,
+                json!({"type": "init", "node_id": node, "node_ids": node_ids}),
+            );
+        }
+        cluster
+    }
+
+    fn send(&mut self, node: &str, mut body: Value) -> usize {
+        self.next_msg_id += 1;
+        body["msg_id"] = json!(self.next_msg_id);
+        let msg = json!({"src": CLIENT, "dest": node, "body": body});
+        write_line(&self.stdins, node, &msg.to_string());
+        self.next_msg_id
+    }
+
+    fn wait(&self, msg_id: usize) -> Value {
+        loop {
+            let reply = self
+                .replies
+                .recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| panic!("No reply to request {}", msg_id));
+            if reply.body.in_reply_to == Some(msg_id) {
+                return reply.body.payload;
+            }
+        }
+    }
+
+    fn call(&mut self, node: &str, body: Value) -> Value {
+        let msg_id = self.send(node, body);
+        self.wait(msg_id)
+    }
+
+    fn add(&mut self, node: &str, element: usize) {
+        self.call(node, json!({"type": "add", "element": element}));
+    }
+
+    fn remove(&mut self, node: &str, element: usize) {
+        self.call(node, json!({"type": "remove", "element": element}));
+    }
+
+    fn read(&mut self, node: &str) -> HashSet<usize> {
+        let reply = self.call(node, json!({"type": "read"}));
+        serde_json::from_value(reply["value"].clone()).expect("Malformed read_ok")
+    }
+
+    fn partition(&self, on: bool) {
+        self.partitioned.store(on, Ordering::SeqCst);
+    }
+
+    fn random_node(&self) -> String {
+        self.nodes.choose(&mut rand::thread_rng()).unwrap().clone()
+    }
+
+    fn stop(mut self) {
+        self.children.iter_mut().for_each(|child| {
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+    }
+}
+
+fn arg(index: usize, default: usize) -> usize {
+    env::args()
+        .nth(index)
+        .and_then(|a| a.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() {
+    let node_count = arg(1, 3).max(2);
+    let element_count = arg(2, 100).max(4);
+
+    let name = env::args().nth(3).unwrap_or("or_set".to_owned());
+    let bin =
+        env::current_exe()
+            .unwrap()
+            .with_file_name(format!("{}{}", name, env::consts::EXE_SUFFIX));
+    let mut cluster = Cluster::start(bin.to_str().unwrap(), node_count);
+    let mut rng = rand::thread_rng();
+
+    // Phase 1: adds everywhere, fully replicated
+    for element in 0..element_count {
+        let node = cluster.random_node();
+        cluster.add(&node, element);
+    }
+    sleep(SETTLE);
+
+    // Phase 2, partitioned: removes of observed elements must stick, a
+    // remove concurrent with an add of the same element loses
+    cluster.partition(true);
+    let (removed, contested) = (element_count / 2, element_count * 3 / 4);
+    for element in 0..removed {
+        let node = cluster.random_node();
+        cluster.remove(&node, element);
+    }
+    for element in removed..contested {
+        let i = rng.gen_range(0..node_count);
+        let j = (i + rng.gen_range(1..node_count)) % node_count;
+        let (remover, adder) = (cluster.nodes[i].clone(), cluster.nodes[j].clone());
+        cluster.remove(&remover, element);
+        cluster.add(&adder, element);
+    }
+    for element in element_count..element_count + element_count / 4 {
+        let node = cluster.random_node();
+        cluster.add(&node, element);
+    }
+
+    // Heal and let the replicas converge
+    cluster.partition(false);
+    sleep(SETTLE);
+
+    let expected: HashSet<usize> = (removed..element_count + element_count / 4).collect();
+    let mut ok = true;
+    for node in cluster.nodes.clone() {
+        let read = cluster.read(&node);
+        if read == expected {
+            println!("{}: ok, {} elements", node, read.len());
+        } else {
+            ok = false;
+            let mut missing: Vec<_> = expected.difference(&read).collect();
+            let mut unexpected: Vec<_> = read.difference(&expected).collect();
+            missing.sort();
+            unexpected.sort();
+            println!(
+                "{}: FAILED, missing {:?}, unexpected {:?}",
+                node, missing, unexpected
+            );
+        }
+    }
+
+    cluster.stop();
+    if !ok {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,243 @@
+// Local checker for the durable Raft state, the same as Maelstrom's
+// `--nemesis kill`: runs a few `raft` nodes storing their state under a
+// temporary RAFT_DATA_DIR, kills and restarts one of them, then the whole
+// cluster, between writes, and checks that every acknowledged write is still
+// read back.
+//
+// usage: raft_restart_check [node count] [write count]
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{self, Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use echo_server::protocol::Message;
+
+const CLIENT: &str = "c1";
+const KEYS: usize = 10;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_ATTEMPTS: usize = 50;
+
+// Killed nodes have no stdin, whatever is sent to them is lost
+type Stdins = Arc<Mutex<HashMap<String, ChildStdin>>>;
+
+struct Cluster {
+    bin: PathBuf,
+    data_dir: PathBuf,
+    nodes: Vec<String>,
+    children: HashMap<String, Child>,
+    stdins: Stdins,
+    replies: Receiver<Message<Value>>,
+    replies_tx: Sender<Message<Value>>,
+    next_msg_id: usize,
+}
+
+fn write_line(stdins: &Stdins, dest: &str, line: &str) {
+    if let Some(stdin) = stdins.lock().unwrap().get_mut(dest) {
+        let _ = writeln!(stdin, "{}", line);
+        let _ = stdin.flush();
+    }
+}
+
+// Forwards node to node traffic, and replies to us
+fn route(stdout: impl BufRead, stdins: Stdins, replies: Sender<Message<Value>>) {
+    for line in stdout.lines().map_while(Result::ok) {
+        let msg: Message<Value> = match serde_json::from_str(&line) {
+            Ok(msg) => msg,
+            Err(_) => continue,
+        };
+        if msg.dest == CLIENT {
+            let _ = replies.send(msg);
+        } else {
+            write_line(&stdins, &msg.dest, &line);
+        }
+    }
+}
+
+impl Cluster {
+    fn start(bin: PathBuf, data_dir: PathBuf, node_count: usize) -> Self {
+        let (replies_tx, replies) = mpsc::channel();
+        let mut cluster = Cluster {
+            bin,
+            data_dir,
+            nodes: (0..node_count).map(|i| format!("n{}", i)).collect(),
+            children: HashMap::new(),
+            stdins: Arc::new(Mutex::new(HashMap::new())),
+            replies,
+            replies_tx,
+            next_msg_id: 0,
+        };
+        cluster
+            .nodes
+            .clone()
+            .iter()
+            .for_each(|node| cluster.spawn(node));
+        cluster
+    }
+
+    fn spawn(&mut self, node: &str) {
+        let mut child = Command::new(&self.bin)
+            .env("RAFT_DATA_DIR", &self.data_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Cannot run {}: {}", self.bin.display(), e));
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        self.stdins.lock().unwrap().insert(node.to_owned(), stdin);
+        self.children.insert(node.to_owned(), child);
+
+        let stdins = Arc::clone(&self.stdins);
+        let tx = self.replies_tx.clone();
+        thread::spawn(move || route(stdout, stdins, tx));
+
+        let init = json!({"type": "init", "node_id": node, "node_ids": self.nodes});
+        let msg_id = self.send(node, init);
+        self.wait(msg_id)
+            .unwrap_or_else(|| panic!("{} did not answer init", node));
+    }
+
+    // As abrupt as a crash: no chance to flush anything
+    fn kill(&mut self, node: &str) {
+        self.stdins.lock().unwrap().remove(node);
+        if let Some(mut child) = self.children.remove(node) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    fn send(&mut self, node: &str, mut body: Value) -> usize {
+        self.next_msg_id += 1;
+        body["msg_id"] = json!(self.next_msg_id);
+        let msg = json!({"src": CLIENT, "dest": node, "body": body});
+        write_line(&self.stdins, node, &msg.to_string());
+        self.next_msg_id
+    }
+
+    fn wait(&self, msg_id: usize) -> Option<Value> {
+        loop {
+            let reply = self.replies.recv_timeout(REPLY_TIMEOUT).ok()?;
+            if reply.body.in_reply_to == Some(msg_id) {
+                return Some(reply.body.payload);
+            }
+        }
+    }
+
+    // Retried on live nodes until it succeeds, while no leader is known
+    // yet or the request timed out. Writes being idempotent, a retry of one
+    // that went through already is harmless.
+    fn call(&mut self, body: Value, ok: &str) -> Value {
+        for _ in 0..MAX_ATTEMPTS {
+            let live: Vec<&String> = self.children.keys().collect();
+            let node = live.choose(&mut rand::thread_rng()).unwrap().to_string();
+            let msg_id = self.send(&node, body.clone());
+            match self.wait(msg_id) {
+                Some(reply) if reply["type"] == ok => return reply,
+                // The key is not written yet, a definite answer
+                Some(reply) if reply["code"] == 20 => return reply,
+                _ => sleep(RETRY_DELAY),
+            }
+        }
+        panic!("No answer to {}", body);
+    }
+
+    fn write(&mut self, key: usize, value: usize) {
+        self.call(
+            json!({"type": "write", "key": key, "value": value}),
+            "write_ok",
+        );
+    }
+
+    fn read(&mut self, key: usize) -> Option<usize> {
+        let reply = self.call(json!({"type": "read", "key": key}), "read_ok");
+        reply["value"].as_u64().map(|value| value as usize)
+    }
+
+    fn stop(mut self) {
+        self.nodes.clone().iter().for_each(|node| self.kill(node));
+    }
+}
+
+fn arg(index: usize, default: usize) -> usize {
+    env::args()
+        .nth(index)
+        .and_then(|a| a.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() {
+    let node_count = arg(1, 3).max(1);
+    let write_count = arg(2, 200).max(KEYS);
+
+    let bin = env::current_exe()
+        .unwrap()
+        .with_file_name(format!("raft{}", env::consts::EXE_SUFFIX));
+    let data_dir = env::temp_dir().join(format!("raft_restart_check-{}", process::id()));
+    let _ = fs::remove_dir_all(&data_dir);
+    let mut cluster = Cluster::start(bin, data_dir.clone(), node_count);
+    let mut expected = HashMap::new();
+
+    let phases = write_count / 3;
+    let mut value = 0;
+    let mut write_phase = |cluster: &mut Cluster, count: usize| {
+        for _ in 0..count {
+            let key = value % KEYS;
+            cluster.write(key, value);
+            expected.insert(key, value);
+            value += 1;
+        }
+    };
+
+    // Phase 1: writes, then one node killed and brought back
+    write_phase(&mut cluster, phases);
+    let node = cluster
+        .nodes
+        .choose(&mut rand::thread_rng())
+        .unwrap()
+        .clone();
+    cluster.kill(&node);
+    write_phase(&mut cluster, phases);
+    cluster.spawn(&node);
+    println!("{} restarted", node);
+
+    // Phase 2: the whole cluster at once, nothing left in memory anywhere
+    write_phase(&mut cluster, write_count - 2 * phases);
+    for node in cluster.nodes.clone() {
+        cluster.kill(&node);
+    }
+    for node in cluster.nodes.clone() {
+        cluster.spawn(&node);
+    }
+    println!("cluster restarted");
+
+    let mut ok = true;
+    for key in 0..KEYS {
+        let read = cluster.read(key);
+        if read == expected.get(&key).copied() {
+            println!("key {}: ok, {:?}", key, read);
+        } else {
+            ok = false;
+            println!(
+                "key {}: FAILED, read {:?}, expected {:?}",
+                key,
+                read,
+                expected.get(&key)
+            );
+        }
+    }
+
+    cluster.stop();
+    let _ = fs::remove_dir_all(&data_dir);
+    if !ok {
+        process::exit(1);
+    }
+}
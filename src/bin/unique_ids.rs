@@ -0,0 +1,6 @@
+use echo_server::runtime;
+use echo_server::unique_ids::node::Node;
+
+fn main() {
+    runtime::run::<Node>();
+}
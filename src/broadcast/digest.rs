@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub const BUCKETS: usize = 32;
+
+// Hashes stay exact integers through Maelstrom's JSON handling
+const HASH_MASK: u64 = (1 << 53) - 1;
+
+// Order independent summary of the values falling in one bucket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BucketDigest {
+    pub count: usize,
+    pub hash: u64,
+}
+
+// splitmix64 finalizer, so that summing hashes does not cancel out
+fn mix(value: usize) -> u64 {
+    let mut z = (value as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn bucket(value: usize) -> usize {
+    value % BUCKETS
+}
+
+pub fn digest(values: &HashSet<usize>) -> Vec<BucketDigest> {
+    let mut buckets = vec![BucketDigest::default(); BUCKETS];
+    values.iter().for_each(|&v| {
+        let b = &mut buckets[bucket(v)];
+        b.count += 1;
+        b.hash = b.hash.wrapping_add(mix(v)) & HASH_MASK;
+    });
+    buckets
+}
+
+// Buckets whose content differs, a malformed digest differs everywhere
+pub fn diff(local: &[BucketDigest], remote: &[BucketDigest]) -> HashSet<usize> {
+    if local.len() != remote.len() {
+        return (0..local.len()).collect();
+    }
+    (0..local.len())
+        .filter(|&i| local[i] != remote[i])
+        .collect()
+}
+
+pub fn in_buckets(values: &HashSet<usize>, buckets: &HashSet<usize>) -> HashSet<usize> {
+    values
+        .iter()
+        .filter(|&&v| buckets.contains(&bucket(v)))
+        .copied()
+        .collect()
+}
@@ -1,2 +1,5 @@
+pub mod digest;
 pub mod msg;
 pub mod node;
+pub mod tasks;
+pub mod topology;
@@ -1,35 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "snake_case")]
-pub struct Message<P: PayloadTrait> {
-    pub src: String,
-    pub dest: String,
-    pub body: Body<P>,
-}
-
-impl<P> Message<P>
-where
-    P: PayloadTrait + Serialize,
-{
-    pub fn new(dest: String, body: Body<P>, src: String) -> Self {
-        Message { src, dest, body }
-    }
-
-    pub fn send(&self) {
-        let mut stdout = io::stdout().lock();
-        serde_json::to_writer(&mut stdout, &self).unwrap();
-        stdout.write_all(b"\n").unwrap();
-        stdout.flush().unwrap();
-    }
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitPayload {
-    pub node_id: String,
-}
+use crate::broadcast::digest::BucketDigest;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TopoPayload {
@@ -57,104 +29,86 @@ impl GossipPayload {
 
 impl RpcTrait for GossipPayload {}
 
+// Batch of values a neighbor has not acknowledged yet
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitOkPayload {
-    in_reply_to: usize,
+pub struct GossipBatchPayload {
+    pub messages: HashSet<usize>,
 }
 
-impl InitOkPayload {
-    pub fn new(in_reply_to: usize) -> Self {
-        InitOkPayload { in_reply_to }
+impl GossipBatchPayload {
+    pub fn new(messages: HashSet<usize>) -> Self {
+        GossipBatchPayload { messages }
     }
 }
 
+// Anti-entropy: summary of the sender's values ...
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct TopologyOkPayload {
-    in_reply_to: usize,
+pub struct SyncPayload {
+    pub digest: Vec<BucketDigest>,
 }
 
-impl TopologyOkPayload {
-    pub fn new(in_reply_to: usize) -> Self {
-        TopologyOkPayload { in_reply_to }
+impl SyncPayload {
+    pub fn new(digest: Vec<BucketDigest>) -> Self {
+        SyncPayload { digest }
     }
 }
 
+// ... answered with the buckets that differ and the receiver's values in them
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ReadOkPayload {
-    in_reply_to: usize,
-    messages: HashSet<usize>,
+pub struct SyncOkPayload {
+    pub buckets: HashSet<usize>,
+    pub messages: HashSet<usize>,
 }
 
-impl ReadOkPayload {
-    pub fn new(in_reply_to: usize, messages: HashSet<usize>) -> Self {
-        ReadOkPayload {
-            in_reply_to,
-            messages,
-        }
+impl SyncOkPayload {
+    pub fn new(buckets: HashSet<usize>, messages: HashSet<usize>) -> Self {
+        SyncOkPayload { buckets, messages }
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct BroadcastOkPayload {
-    pub in_reply_to: usize,
+pub struct ReadOkPayload {
+    messages: HashSet<usize>,
 }
 
-impl BroadcastOkPayload {
-    pub fn new(in_reply_to: usize) -> Self {
-        BroadcastOkPayload { in_reply_to }
+impl ReadOkPayload {
+    pub fn new(messages: HashSet<usize>) -> Self {
+        ReadOkPayload { messages }
     }
 }
 
-pub trait PayloadTrait {}
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 //#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum ReqPayload {
-    #[serde(rename = "init")]
-    Init(InitPayload),
     #[serde(rename = "topology")]
     Topology(TopoPayload),
     #[serde(rename = "read")]
     Read,
     #[serde(rename = "broadcast")]
     Broadcast(GossipPayload),
-    #[serde(rename = "broadcast_ok")]
-    InterServerGossipOk(BroadcastOkPayload),
+    #[serde(rename = "gossip")]
+    Gossip(GossipBatchPayload),
+    #[serde(rename = "sync")]
+    Sync(SyncPayload),
 }
 
-impl PayloadTrait for ReqPayload {}
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 //#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum ReplyPayload {
-    #[serde(rename = "init_ok")]
-    InitOk(InitOkPayload),
     #[serde(rename = "topology_ok")]
-    TopologyOk(TopologyOkPayload),
+    TopologyOk,
     #[serde(rename = "read_ok")]
     ReadOk(ReadOkPayload),
-    #[serde(rename = "broadcast")]
-    Gossip(GossipPayload),
     #[serde(rename = "broadcast_ok")]
-    BroadcastOk(BroadcastOkPayload),
-}
-
-impl PayloadTrait for ReplyPayload {}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Body<P: PayloadTrait> {
-    #[serde(flatten)]
-    pub payload: P,
-    pub msg_id: usize,
-}
-
-impl<P> Body<P>
-where
-    P: PayloadTrait,
-{
-    pub fn new(payload: P, msg_id: usize) -> Self {
-        Body { payload, msg_id }
-    }
+    BroadcastOk,
+    #[serde(rename = "gossip")]
+    Gossip(GossipBatchPayload),
+    #[serde(rename = "gossip_ok")]
+    GossipOk,
+    #[serde(rename = "sync")]
+    Sync(SyncPayload),
+    #[serde(rename = "sync_ok")]
+    SyncOk(SyncOkPayload),
 }
@@ -1,90 +1,45 @@
-use crate::broadcast::msg::{
-    Body, BroadcastOkPayload, GossipPayload, InitOkPayload, Message, TopologyOkPayload,
-};
+use crate::broadcast::digest::{diff, digest, in_buckets};
+use crate::broadcast::msg::SyncOkPayload;
+use crate::broadcast::tasks::{anti_entropy, gossip_deltas};
+use crate::broadcast::topology::Overlay;
+use crate::protocol::{ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::env_usize;
+use crate::runtime::handler::Handler;
+use crate::runtime::task::Task;
 
-use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
-use std::sync::{Arc, Mutex, RwLock};
-use std::thread::{self, sleep, JoinHandle};
-use std::time::Duration;
+use std::sync::{Arc, RwLock};
 
 use super::msg::{ReadOkPayload, ReplyPayload, ReqPayload};
 
-#[derive(Debug)]
-struct Rpc {
-    done: Arc<Mutex<bool>>,
-    join_handle: Option<JoinHandle<()>>,
-}
-
-impl Rpc {
-    pub fn new() -> Self {
-        Rpc {
-            done: Arc::new(Mutex::new(false)),
-            join_handle: None,
-        }
-    }
-
-    pub fn replied(&mut self) {
-        *self.done.lock().unwrap() = true
-    }
-
-    pub fn detach(&mut self, msg: Message<ReplyPayload>) {
-        let done_clone = Arc::clone(&self.done);
-
-        self.join_handle = Some(thread::spawn(move || loop {
-            if let Ok(done_guard) = done_clone.lock() {
-                if !*done_guard {
-                    eprintln!("Retrying: {:?}", msg);
-                    msg.send();
-                } else {
-                    break;
-                }
-            }
-            sleep(Duration::from_millis(10));
-        }));
-    }
+// GOSSIP_INTERVAL (ms) trades messages per operation for latency
+pub const DEFAULT_GOSSIP_INTERVAL: u64 = 100;
+// ANTI_ENTROPY_INTERVAL (ms) bounds how long a repair takes once a
+// partition heals
+pub const DEFAULT_ANTI_ENTROPY_INTERVAL: u64 = 1000;
+// Above a round trip under Maelstrom's --latency 100
+pub const DEFAULT_GOSSIP_TIMEOUT: u64 = 500;
+
+// GOSSIP_TIMEOUT (ms) before a batch is sent again, independent of the
+// interval: shorter than a round trip, every batch would be sent twice
+pub fn gossip_timeout() -> u64 {
+    env_usize("GOSSIP_TIMEOUT").map_or(DEFAULT_GOSSIP_TIMEOUT, |ms| ms.max(1) as u64)
 }
 
 #[derive(Debug)]
 pub struct Node {
-    node_id: String,
-    next_msg_id: RwLock<usize>,
-    msg_set: RwLock<HashSet<usize>>,
-    neighbors: RwLock<HashSet<String>>,
-    // rpc_wait_map: RwLock<HashMap<String, Vec<Body<ReplyPayload>>>>,
-    rpc_wait_map: RwLock<HashMap<(String, usize), Rpc>>,
+    pub ctx: Arc<Context>,
+    pub msg_set: Arc<RwLock<HashSet<usize>>>,
+    pub neighbors: RwLock<HashSet<String>>,
+    overlay: Overlay,
+    // Values each neighbor is known to have, either acked or sent by it
+    pub known: Arc<RwLock<HashMap<String, HashSet<usize>>>>,
+    // Neighbors with a gossip batch still being retransmitted
+    pub in_flight: Arc<RwLock<HashSet<String>>>,
 }
 
 impl Node {
-    pub fn new(node_id: String) -> Self {
-        Node {
-            node_id,
-            next_msg_id: RwLock::new(0),
-            msg_set: RwLock::new(HashSet::new()),
-            neighbors: RwLock::new(HashSet::new()),
-            // rpc_wait_map: RwLock::new(HashMap::new()),
-            rpc_wait_map: RwLock::new(HashMap::new()),
-        }
-    }
-
-    fn log<M>(&self, msg: &M)
-    where
-        M: Serialize,
-    {
-        let mut stderr = io::stderr();
-        serde_json::to_writer(&stderr, &msg).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-    }
-
-    fn build_body(&self, payload: ReplyPayload) -> Body<ReplyPayload> {
-        let body = Body::new(payload, self.next_msg_id.read().unwrap().clone());
-        let mut nxt_msg_id_guard = self.next_msg_id.write().unwrap();
-        *nxt_msg_id_guard += 1;
-        body
-    }
-
     fn clone_set(&self) -> HashSet<usize> {
         self.msg_set.read().unwrap().clone()
     }
@@ -93,104 +48,116 @@ impl Node {
         self.msg_set.write().unwrap().insert(msg)
     }
 
-    pub fn kill_retry(&self) {
-        self.rpc_wait_map
+    pub fn mark_known(&self, neighbor: &str, messages: &HashSet<usize>) {
+        self.known
             .write()
             .unwrap()
-            .iter_mut()
-            .for_each(|(_, v)| {
-                if let Some(join_handle) = v.join_handle.take() {
-                    join_handle.join().unwrap();
-                }
-            });
+            .entry(neighbor.to_owned())
+            .or_default()
+            .extend(messages);
     }
 
-    pub fn handle_msg(&self, request: &mut Message<ReqPayload>) -> Result<(), ()> {
-        self.log(&format!("Received {:?}", request));
+    // Values not acknowledged yet by each neighbor without a batch in flight
+    pub fn deltas(&self) -> Vec<(String, HashSet<usize>)> {
+        let msg_set = self.msg_set.read().unwrap();
+        let known = self.known.read().unwrap();
+        let in_flight = self.in_flight.read().unwrap();
+        self.neighbors
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|n| !in_flight.contains(*n))
+            .filter_map(|n| {
+                let delta: HashSet<usize> = match known.get(n) {
+                    Some(known_set) => msg_set.difference(known_set).copied().collect(),
+                    None => msg_set.clone(),
+                };
+                (!delta.is_empty()).then(|| (n.clone(), delta))
+            })
+            .collect()
+    }
+}
 
-        let req_msg_id = request.body.msg_id;
+impl Handler for Node {
+    type Payload = ReqPayload;
+
+    fn init(ctx: Arc<Context>) -> Self {
+        let overlay = Overlay::from_env();
+        ctx.log(&format!(
+            "Initiated node {:} with overlay {:?}",
+            ctx.node_id, overlay
+        ));
+        Node {
+            ctx,
+            msg_set: Arc::new(RwLock::new(HashSet::new())),
+            neighbors: RwLock::new(HashSet::new()),
+            overlay,
+            known: Arc::new(RwLock::new(HashMap::new())),
+            in_flight: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    fn handle(&self, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
+        self.ctx.log(&format!("Received {:?}", request));
 
         // effect from request
-        match &mut request.body.payload {
-            ReqPayload::Init(init_p) => {
-                self.log(&format!("Initiated node {:}", init_p.node_id));
-            }
+        match &request.body.payload {
             ReqPayload::Topology(topo_p) => {
-                if let Some(neighbors) = topo_p.topology.remove(&self.node_id) {
-                    self.log(&format!("My neighbours are {:?}", neighbors));
-                    let mut write_guard = self.neighbors.write().unwrap();
-                    *write_guard = neighbors;
+                let neighbors =
+                    self.overlay
+                        .neighbors(&self.ctx.node_id, &self.ctx.node_ids, &topo_p.topology);
+                if neighbors.is_empty() {
+                    self.ctx.log(&format!(
+                        "No neighbours found for node {:?}",
+                        self.ctx.node_id
+                    ));
                 } else {
-                    self.log(&format!("No neighbours found for node {:?}", self.node_id));
+                    self.ctx.log(&format!("My neighbours are {:?}", neighbors));
                 }
+                *self.neighbors.write().unwrap() = neighbors;
             }
+            // store message, gossiped to neighbors with the next batch
             ReqPayload::Broadcast(broadcast_p) => {
-                // store message
-                // + inter-server message (if needed)
-
-                // if message not yet inserted in set...
-                if self.insert_msg(broadcast_p.message.clone()) {
-                    // ... gossip to all neighbors ...
-                    let req_src = &request.src;
-                    self.neighbors
-                        .read()
-                        .unwrap()
-                        .iter()
-                        // ... except from the message sender in case it is a neighbor
-                        .filter(|&n| n != req_src)
-                        .map(|n| (broadcast_p.message.clone(), n.clone()))
-                        .for_each(|(msg, n)| {
-                            //  prepare body for rpc
-                            let payload = ReplyPayload::Gossip(GossipPayload::new(msg));
-                            let body = self.build_body(payload);
-
-                            // store rpc in rpc_wait_map_aux
-                            let msg = Message::new(n.to_owned(), body, self.node_id.clone());
-                            let rpc_msg_id = msg.body.msg_id;
-                            let mut rpc = Rpc::new();
-                            rpc.detach(msg);
-
-                            let mut rpc_map_guard_aux = self.rpc_wait_map.write().unwrap();
-                            rpc_map_guard_aux.insert((n.to_owned(), rpc_msg_id), rpc);
-                        });
-                }
+                self.insert_msg(broadcast_p.message);
             }
-            ReqPayload::InterServerGossipOk(gossip_ok_p) => {
-                // remove message from rpc_wait_map
-                let mut rpc_map_guard_aux = self.rpc_wait_map.write().unwrap();
-                if let Some(rpc) =
-                    rpc_map_guard_aux.get_mut(&(request.src.to_string(), gossip_ok_p.in_reply_to))
-                {
-                    self.log(&format!("RPC ACK {:?}", request.src));
-                    rpc.replied();
-                }
+            // the sender obviously has what it sent, no need to send it back
+            ReqPayload::Gossip(gossip_p) => {
+                self.msg_set
+                    .write()
+                    .unwrap()
+                    .extend(gossip_p.messages.iter().copied());
+                self.mark_known(&request.src, &gossip_p.messages);
             }
-            _ => (),
+            ReqPayload::Sync(_) | ReqPayload::Read => (),
         };
 
         // send response
-        let reply_payload_opt = match request.body.payload {
-            ReqPayload::Init(_) => Some(ReplyPayload::InitOk(InitOkPayload::new(req_msg_id))),
-            ReqPayload::Topology(_) => {
-                Some(ReplyPayload::TopologyOk(TopologyOkPayload::new(req_msg_id)))
+        let reply_payload = match &request.body.payload {
+            ReqPayload::Topology(_) => ReplyPayload::TopologyOk,
+            ReqPayload::Broadcast(_) => ReplyPayload::BroadcastOk,
+            ReqPayload::Gossip(_) => ReplyPayload::GossipOk,
+            // the sender pushes back what we miss in the differing buckets
+            ReqPayload::Sync(sync_p) => {
+                let msg_set = self.msg_set.read().unwrap();
+                let buckets = diff(&digest(&msg_set), &sync_p.digest);
+                let messages = in_buckets(&msg_set, &buckets);
+                ReplyPayload::SyncOk(SyncOkPayload::new(buckets, messages))
             }
-            ReqPayload::Broadcast(_) => Some(ReplyPayload::BroadcastOk(BroadcastOkPayload::new(
-                req_msg_id,
-            ))),
             ReqPayload::Read => {
                 let set_clone = self.clone_set();
-                Some(ReplyPayload::ReadOk(ReadOkPayload::new(
-                    req_msg_id, set_clone,
-                )))
+                ReplyPayload::ReadOk(ReadOkPayload::new(set_clone))
             }
-            _ => None,
         };
 
-        if let Some(payload) = reply_payload_opt {
-            let body = self.build_body(payload);
-            Message::new(request.src.to_owned(), body, self.node_id.clone()).send();
-        }
+        self.ctx.reply(&request, reply_payload);
 
         Ok(())
     }
+
+    fn tasks() -> Vec<Task<Self>> {
+        vec![
+            Task::new("gossip", DEFAULT_GOSSIP_INTERVAL, gossip_deltas),
+            Task::new("anti_entropy", DEFAULT_ANTI_ENTROPY_INTERVAL, anti_entropy).with_jitter(0.2),
+        ]
+    }
 }
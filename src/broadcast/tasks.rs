@@ -0,0 +1,118 @@
+use rand::seq::IteratorRandom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::broadcast::digest::{digest, in_buckets};
+use crate::broadcast::msg::{GossipBatchPayload, ReplyPayload, SyncOkPayload, SyncPayload};
+use crate::broadcast::node::{gossip_timeout, Node, DEFAULT_ANTI_ENTROPY_INTERVAL};
+use crate::runtime::rpc::{RetryPolicy, RpcOptions};
+
+pub const GOSSIP_MAX_ATTEMPTS: usize = 5;
+
+// One batch per neighbor holding everything it has not acked, retransmitted
+// with backoff until acked or abandoned. Values arriving meanwhile wait for
+// the next batch, so a dead neighbor costs a few messages per round, not
+// one stream per value.
+pub fn gossip_deltas(node: &Node) {
+    let timeout = Duration::from_millis(gossip_timeout());
+    let opts = RpcOptions::new(timeout).with_retry(RetryPolicy::exponential(
+        Some(GOSSIP_MAX_ATTEMPTS),
+        timeout * 16,
+    ));
+
+    node.deltas().into_iter().for_each(|(n, delta)| {
+        let known = Arc::clone(&node.known);
+        let in_flight = Arc::clone(&node.in_flight);
+        let ctx = Arc::clone(&node.ctx);
+        let dest = n.clone();
+        let payload = ReplyPayload::Gossip(GossipBatchPayload::new(delta.clone()));
+
+        node.in_flight.write().unwrap().insert(n.clone());
+        node.ctx.rpc(&n, payload, opts, move |result| {
+            match result {
+                Ok(_) => known
+                    .write()
+                    .unwrap()
+                    .entry(dest.clone())
+                    .or_default()
+                    .extend(delta),
+                Err(e) => ctx.log(&format!("Gossip to {} abandoned: {}", dest, e)),
+            };
+            in_flight.write().unwrap().remove(&dest);
+        });
+    });
+}
+
+// Compare digests with a random node, any node rather than a neighbor so
+// that a dead relay in the overlay does not cut the others off. Both sides
+// then exchange what the other misses in the buckets that differ. A lost
+// exchange is not retried, the next round makes up for it.
+pub fn anti_entropy(node: &Node) {
+    let peer = match node
+        .ctx
+        .neighbors()
+        .into_iter()
+        .choose(&mut rand::thread_rng())
+    {
+        Some(peer) => peer,
+        None => return,
+    };
+
+    node.ctx
+        .log(&format!("RPC stats {:?}", node.ctx.rpc_stats()));
+
+    // An exchange lasts no longer than a round
+    let interval = node.ctx.scheduler.interval("anti_entropy");
+    let opts = RpcOptions::new(
+        interval.unwrap_or_else(|| Duration::from_millis(DEFAULT_ANTI_ENTROPY_INTERVAL)),
+    );
+    let payload = ReplyPayload::Sync(SyncPayload::new(digest(&node.msg_set.read().unwrap())));
+
+    let ctx = Arc::clone(&node.ctx);
+    let msg_set = Arc::clone(&node.msg_set);
+    let known = Arc::clone(&node.known);
+    let dest = peer.clone();
+    node.ctx.rpc(&peer, payload, opts, move |result| {
+        let SyncOkPayload { buckets, messages } = match result.map(|r| r.into_typed()) {
+            Ok(Ok(reply)) => match reply.body.payload {
+                ReplyPayload::SyncOk(sync_ok_p) => sync_ok_p,
+                _ => return,
+            },
+            Ok(Err(e)) | Err(e) => {
+                ctx.log(&format!("Anti-entropy with {} failed: {}", dest, e));
+                return;
+            }
+        };
+        if buckets.is_empty() {
+            return;
+        }
+
+        let missing = {
+            let mut msg_set_guard = msg_set.write().unwrap();
+            msg_set_guard.extend(messages.iter().copied());
+            &in_buckets(&msg_set_guard, &buckets) - &messages
+        };
+        known
+            .write()
+            .unwrap()
+            .entry(dest.clone())
+            .or_default()
+            .extend(messages);
+
+        if !missing.is_empty() {
+            ctx.log(&format!("Anti-entropy repairs {:?} on {}", missing, dest));
+            let payload = ReplyPayload::Gossip(GossipBatchPayload::new(missing.clone()));
+            let repaired = dest.clone();
+            ctx.rpc(&dest, payload, opts, move |result| {
+                if result.is_ok() {
+                    known
+                        .write()
+                        .unwrap()
+                        .entry(repaired)
+                        .or_default()
+                        .extend(missing);
+                }
+            });
+        }
+    });
+}
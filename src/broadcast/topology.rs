@@ -0,0 +1,149 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::str::FromStr;
+
+pub type Topology = HashMap<String, HashSet<String>>;
+
+// Same seed on every node, so that they all build the same random graph
+const RANDOM_SEED: u64 = 0x6d61_656c;
+// Degree of the `tree` overlay: 25 nodes are at most 3 hops from the root
+const TREE_DEGREE: usize = 4;
+
+// Overlay the broadcast node gossips on, chosen at startup with TOPOLOGY:
+// provided (default), tree (same as kary:4), star, kary:<k>, random:<k>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overlay {
+    // Neighbors given by Maelstrom's topology message, a grid by default
+    #[default]
+    Provided,
+    // Every node linked to a single hub, two hops between any two nodes
+    Star,
+    // Node i is the parent of nodes k*i+1 ..= k*i+k, no redundant paths
+    KaryTree(usize),
+    // Every node has k neighbors picked at random, k >= 2
+    RandomRegular(usize),
+}
+
+impl FromStr for Overlay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let k = |min: usize| match arg.map(str::parse::<usize>) {
+            Some(Ok(k)) if k >= min => Ok(k),
+            _ => Err(format!(
+                "Overlay {} expects a degree of {} at least, as in {}:3",
+                name, min, name
+            )),
+        };
+
+        match name.trim().to_lowercase().as_str() {
+            "provided" | "grid" => Ok(Overlay::Provided),
+            "tree" | "spanning_tree" => Ok(Overlay::KaryTree(TREE_DEGREE)),
+            "star" => Ok(Overlay::Star),
+            "kary" => k(1).map(Overlay::KaryTree),
+            // A single neighbor each only pairs nodes up
+            "random" => k(2).map(Overlay::RandomRegular),
+            _ => Err(format!("Unknown overlay {}", s)),
+        }
+    }
+}
+
+impl Overlay {
+    pub fn from_env() -> Self {
+        match env::var("TOPOLOGY").map(|s| s.parse()) {
+            Ok(Ok(overlay)) => overlay,
+            Ok(Err(e)) => {
+                eprintln!("{}, using the provided topology", e);
+                Overlay::Provided
+            }
+            Err(_) => Overlay::Provided,
+        }
+    }
+
+    // Neighbors of `node_id`, every node computes the same overlay from the
+    // same inputs
+    pub fn neighbors(
+        &self,
+        node_id: &str,
+        node_ids: &HashSet<String>,
+        provided: &Topology,
+    ) -> HashSet<String> {
+        let mut topology = match self {
+            Overlay::Provided => provided.clone(),
+            Overlay::Star => star(&sorted(node_ids)),
+            Overlay::KaryTree(k) => kary_tree(&sorted(node_ids), *k),
+            Overlay::RandomRegular(k) => random_regular(&sorted(node_ids), *k),
+        };
+        topology.remove(node_id).unwrap_or_default()
+    }
+}
+
+fn sorted(node_ids: &HashSet<String>) -> Vec<String> {
+    let mut nodes: Vec<String> = node_ids.iter().cloned().collect();
+    nodes.sort();
+    nodes
+}
+
+fn link(topology: &mut Topology, a: &str, b: &str) {
+    if a != b {
+        topology
+            .entry(a.to_owned())
+            .or_default()
+            .insert(b.to_owned());
+        topology
+            .entry(b.to_owned())
+            .or_default()
+            .insert(a.to_owned());
+    }
+}
+
+fn star(nodes: &[String]) -> Topology {
+    let mut topology = Topology::new();
+    if let Some((hub, others)) = nodes.split_first() {
+        others.iter().for_each(|n| link(&mut topology, hub, n));
+    }
+    topology
+}
+
+fn kary_tree(nodes: &[String], k: usize) -> Topology {
+    let mut topology = Topology::new();
+    (1..nodes.len()).for_each(|i| link(&mut topology, &nodes[(i - 1) / k], &nodes[i]));
+    topology
+}
+
+// Circulant graph over a shuffled ring: each node is linked to the k/2
+// following ones, plus the opposite one when k is odd. Connected for k >= 2,
+// and k-regular unless both k and the node count are odd (then k-1).
+fn random_regular(nodes: &[String], k: usize) -> Topology {
+    // Also when built without `from_str`
+    let k = k.max(2);
+    let n = nodes.len();
+    if k + 1 >= n {
+        let mut topology = Topology::new();
+        nodes
+            .iter()
+            .for_each(|a| nodes.iter().for_each(|b| link(&mut topology, a, b)));
+        return topology;
+    }
+
+    let mut ring = nodes.to_vec();
+    ring.shuffle(&mut StdRng::seed_from_u64(RANDOM_SEED));
+
+    let mut topology = Topology::new();
+    for i in 0..n {
+        for step in 1..=k / 2 {
+            link(&mut topology, &ring[i], &ring[(i + step) % n]);
+        }
+        if !k.is_multiple_of(2) && n.is_multiple_of(2) {
+            link(&mut topology, &ring[i], &ring[(i + n / 2) % n]);
+        }
+    }
+    topology
+}
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::{CrdtTrait, OpCrdtTrait};
+use crate::crdt::msg::OpOkPayload;
+use crate::crdt::vclock::VClock;
+use crate::protocol::ErrorPayload;
+
+// Effect of an operation, stamped with the clock of its origin once
+// applied there
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CausalOp<E> {
+    pub origin: String,
+    pub clock: VClock,
+    pub effect: E,
+}
+
+// Holds back operations until everything they depend on is delivered
+#[derive(Debug)]
+pub struct CausalBuffer<E> {
+    delivered: VClock,
+    pending: Vec<CausalOp<E>>,
+}
+
+impl<E> Default for CausalBuffer<E> {
+    fn default() -> Self {
+        CausalBuffer {
+            delivered: VClock::default(),
+            pending: vec![],
+        }
+    }
+}
+
+impl<E> CausalBuffer<E> {
+    pub fn delivered(&self) -> &VClock {
+        &self.delivered
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn is_duplicate(&self, op: &CausalOp<E>) -> bool {
+        let counter = op.clock.get(&op.origin);
+        counter <= self.delivered.get(&op.origin)
+            || self
+                .pending
+                .iter()
+                .any(|p| p.origin == op.origin && p.clock.get(&p.origin) == counter)
+    }
+
+    // Stamps a local operation, delivered right away
+    pub fn stamp(&mut self, origin: &str, effect: E) -> CausalOp<E> {
+        self.delivered.increment(origin);
+        CausalOp {
+            origin: origin.to_owned(),
+            clock: self.delivered.clone(),
+            effect,
+        }
+    }
+
+    // Returns the operations now deliverable, in causal order. Duplicates
+    // are dropped, so that delivery is exactly once.
+    pub fn receive(&mut self, op: CausalOp<E>) -> Vec<CausalOp<E>> {
+        if !self.is_duplicate(&op) {
+            self.pending.push(op);
+        }
+
+        let mut ready = vec![];
+        while let Some(i) = self
+            .pending
+            .iter()
+            .position(|p| p.clock.deliverable(&p.origin, &self.delivered))
+        {
+            let op = self.pending.swap_remove(i);
+            self.delivered.merge(&op.clock);
+            ready.push(op);
+        }
+        ready
+    }
+}
+
+// Operations a node delivered, kept until every peer delivered them too:
+// what a peer misses is sent from here, in causal order
+#[derive(Debug)]
+pub struct CausalLog<E> {
+    buffer: CausalBuffer<E>,
+    history: Vec<CausalOp<E>>,
+    // What each peer acked having delivered
+    peers: HashMap<String, VClock>,
+}
+
+impl<E> Default for CausalLog<E> {
+    fn default() -> Self {
+        CausalLog {
+            buffer: CausalBuffer::default(),
+            history: vec![],
+            peers: HashMap::new(),
+        }
+    }
+}
+
+impl<E: Clone> CausalLog<E> {
+    pub fn delivered(&self) -> &VClock {
+        self.buffer.delivered()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.buffer.pending()
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn stamp(&mut self, origin: &str, effect: E) {
+        let op = self.buffer.stamp(origin, effect);
+        self.history.push(op);
+    }
+
+    // Returns the effects now deliverable, in causal order
+    pub fn receive(&mut self, op: CausalOp<E>) -> Vec<E> {
+        let ready = self.buffer.receive(op);
+        let effects = ready.iter().map(|op| op.effect.clone()).collect();
+        self.history.extend(ready);
+        effects
+    }
+
+    // Up to `max` operations the peer did not ack yet, oldest first
+    pub fn missing(&self, peer: &str, max: usize) -> Vec<CausalOp<E>> {
+        let acked = self.peers.get(peer);
+        self.history
+            .iter()
+            .filter(|op| acked.is_none_or(|clock| !op.clock.leq(clock)))
+            .take(max)
+            .cloned()
+            .collect()
+    }
+
+    pub fn ack(&mut self, peer: &str, delivered: &VClock) {
+        self.peers
+            .entry(peer.to_owned())
+            .or_default()
+            .merge(delivered);
+    }
+
+    // Forget the operations every peer delivered
+    pub fn prune(&mut self, peers: &HashSet<String>) {
+        let CausalLog {
+            history,
+            peers: acked_by,
+            ..
+        } = self;
+        let acked: Vec<&VClock> = peers.iter().filter_map(|p| acked_by.get(p)).collect();
+        if acked.len() < peers.len() {
+            return;
+        }
+        history.retain(|op| !acked.iter().all(|clock| op.clock.leq(clock)));
+    }
+}
+
+// Runs an operation-based CRDT on `Node`: its deltas are batches of
+// effects, that the node stamps with a vector clock and every replica
+// applies in causal order
+#[derive(Debug)]
+pub struct Causal<C: OpCrdtTrait> {
+    crdt: C,
+    // Local effects not replicated yet. Also held while effects are
+    // applied, so that no remote one slips in between a prepare and its
+    // effect.
+    outbox: RwLock<Vec<C::Effect>>,
+}
+
+impl<C: OpCrdtTrait> CrdtTrait for Causal<C> {
+    type Op = C::Op;
+    type State = Vec<C::Effect>;
+    type Value = C::Value;
+
+    const CAUSAL: bool = true;
+
+    fn new(node_id: &str, neighbors: &HashSet<String>) -> Self {
+        Causal {
+            crdt: C::new(node_id, neighbors),
+            outbox: RwLock::new(vec![]),
+        }
+    }
+
+    fn apply(&self, op: C::Op) -> Result<OpOkPayload, ErrorPayload> {
+        let mut outbox = self.outbox.write().unwrap();
+        let (ok, effect_opt) = self.crdt.prepare(op)?;
+        if let Some(effect) = effect_opt {
+            self.crdt.effect(effect.clone());
+            outbox.push(effect);
+        }
+        Ok(ok)
+    }
+
+    // There is no state to send: peers catch up on the operations they
+    // miss from the node's `CausalLog`
+    fn data(&self) -> Self::State {
+        vec![]
+    }
+
+    fn take_delta(&self) -> Option<Self::State> {
+        let outbox = std::mem::take(&mut *self.outbox.write().unwrap());
+        (!outbox.is_empty()).then_some(outbox)
+    }
+
+    fn read(&self) -> C::Value {
+        self.crdt.read()
+    }
+
+    fn read_key(&self, key: &serde_json::Value) -> Result<serde_json::Value, ErrorPayload> {
+        self.crdt.read_key(key)
+    }
+
+    // Effects delivered in causal order
+    fn merge(&self, effects: Self::State) {
+        let _outbox = self.outbox.write().unwrap();
+        effects
+            .into_iter()
+            .for_each(|effect| self.crdt.effect(effect));
+    }
+}
@@ -1,46 +1,72 @@
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
 
-pub enum CrdtElem {
-    GSetElem(usize),
-    PNCounterDelta(String, i64),
-}
+use crate::crdt::msg::OpOkPayload;
+use crate::protocol::{ErrorCode, ErrorPayload};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub enum CrdtData {
-    GSetData(HashSet<usize>),
-    PNCounterData((HashMap<String, i64>, HashMap<String, i64>)),
-}
+// A node generic over `C: CrdtTrait` only parses the operations and states
+// of `C`, anything else is rejected as a malformed request.
+pub trait CrdtTrait: Send + Sync + 'static {
+    // Client operations, tagged by their message `type`
+    type Op: Clone + std::fmt::Debug + DeserializeOwned + Serialize + Send + 'static;
+    // Replicated state, deltas included
+    type State: Clone + std::fmt::Debug + DeserializeOwned + Serialize + Send + Sync + 'static;
+    // Answer to a read
+    type Value: Serialize;
+    // Deltas must be merged in causal order, see `crdt::causal`
+    const CAUSAL: bool = false;
+
+    fn new(node_id: &str, neighbors: &HashSet<String>) -> Self;
 
-/*impl CrdtData {
-    pub fn from_json(value: serde_json::Value) -> Self {
-        eprintln!("value: {:?}", value);
-        match value {
-            serde_json::Value::Array(arr_val) => match arr_val {
-                serde_json::Value::Array(arr_val) => {
-                    CrdtData::GSetData(serde_json::from_value(value).unwrap())
-                }
-                serde_json::Value::Object(_) => {
-                    CrdtData::PNCounterData(serde_json::from_value(value).unwrap())
-                }
-                _ => {
-                    panic!("Wrong element type");
-                }
-            },
-            _ => {
-                panic!("Wrong element type");
-            }
-        }
+    fn apply(&self, op: Self::Op) -> Result<OpOkPayload, ErrorPayload>;
+    fn data(&self) -> Self::State;
+    // Local changes since the previous call, None if nothing changed
+    fn take_delta(&self) -> Option<Self::State>;
+    fn read(&self) -> Self::Value;
+    // Keyed CRDTs only, each parses the key into its own key type
+    fn read_key(&self, _key: &serde_json::Value) -> Result<serde_json::Value, ErrorPayload> {
+        Err(ErrorPayload::new(
+            ErrorCode::NotSupported,
+            "Read by key not supported".to_owned(),
+        ))
     }
-}*/
+    fn merge(&self, other: Self::State);
+    // Deltas are states of the same lattice, merged the same way
+    fn merge_delta(&self, delta: Self::State) {
+        self.merge(delta);
+    }
+}
 
-pub trait CrdtTrait {
-    fn new(neighbors: &HashSet<String>) -> Self;
-    //fn from_json(value: serde_json::Value) -> Self;
+// Operation-based CRDTs: an operation is prepared on the replica it is
+// sent to, and its effect applied on every replica, in causal order. Run
+// on a node through `crdt::causal::Causal`.
+pub trait OpCrdtTrait: Send + Sync + 'static {
+    type Op: Clone + std::fmt::Debug + DeserializeOwned + Serialize + Send + 'static;
+    // What replicas apply, carried by replication messages
+    type Effect: Clone + std::fmt::Debug + DeserializeOwned + Serialize + Send + Sync + 'static;
+    type Value: Serialize;
+
+    fn new(node_id: &str, neighbors: &HashSet<String>) -> Self;
+
+    // Reads the local state only, None when there is nothing to replicate
+    fn prepare(&self, op: Self::Op) -> Result<(OpOkPayload, Option<Self::Effect>), ErrorPayload>;
+    fn effect(&self, effect: Self::Effect);
+    fn read(&self) -> Self::Value;
+    fn read_key(&self, _key: &serde_json::Value) -> Result<serde_json::Value, ErrorPayload> {
+        Err(ErrorPayload::new(
+            ErrorCode::NotSupported,
+            "Read by key not supported".to_owned(),
+        ))
+    }
+}
 
-    fn add(&self, element: CrdtElem);
-    //fn to_json(&self) -> serde_json::Value;
-    fn data(&self) -> CrdtData;
-    fn read_json(&self) -> serde_json::Value;
-    fn merge(&self, other: CrdtData);
+// Keys of reads come as raw JSON
+pub fn parse_key<K: DeserializeOwned>(key: &serde_json::Value) -> Result<K, ErrorPayload> {
+    serde_json::from_value(key.clone()).map_err(|e| {
+        ErrorPayload::new(
+            ErrorCode::MalformedRequest,
+            format!("Invalid key {}: {}", key, e),
+        )
+    })
 }
@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+// Past this many deltas the oldest are dropped, peers that still miss them
+// get the full state instead
+pub const MAX_DELTAS: usize = 1024;
+
+// Local deltas numbered in order, kept until every peer acked them
+#[derive(Debug)]
+pub struct DeltaLog<S> {
+    // Sequence number of the first delta kept
+    base: u64,
+    deltas: VecDeque<S>,
+}
+
+impl<S> Default for DeltaLog<S> {
+    fn default() -> Self {
+        DeltaLog {
+            base: 0,
+            deltas: VecDeque::new(),
+        }
+    }
+}
+
+impl<S: Clone> DeltaLog<S> {
+    // Sequence number of the next delta, i.e. how many were pushed so far
+    pub fn head(&self) -> u64 {
+        self.base + self.deltas.len() as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn push(&mut self, delta: S) {
+        self.deltas.push_back(delta);
+        if self.deltas.len() > MAX_DELTAS {
+            self.deltas.pop_front();
+            self.base += 1;
+        }
+    }
+
+    // Deltas a peer that acked up to `acked` misses, None once dropped
+    pub fn since(&self, acked: u64) -> Option<Vec<S>> {
+        let skip = acked.checked_sub(self.base)? as usize;
+        Some(self.deltas.iter().skip(skip).cloned().collect())
+    }
+
+    // Forget the deltas every peer acked
+    pub fn truncate(&mut self, acked_by_all: u64) {
+        while self.base < acked_by_all && !self.deltas.is_empty() {
+            self.deltas.pop_front();
+            self.base += 1;
+        }
+    }
+}
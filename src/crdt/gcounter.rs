@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::msg::{DeltaPayload, OpOkPayload};
+use crate::protocol::{ErrorCode, ErrorPayload};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum CounterOp {
+    #[serde(rename = "add")]
+    Add(DeltaPayload),
+}
+
+// Grow-only counter: each node only increments its own entry, merge keeps
+// the max per node, the value is the sum of all entries.
+#[derive(Debug)]
+pub struct GCounter {
+    node_id: String,
+    data: RwLock<HashMap<String, u64>>,
+    // Nodes incremented since the last delta
+    dirty: RwLock<HashSet<String>>,
+}
+
+impl GCounter {
+    pub fn increment(&self, node: String, x: u64) {
+        *self.data.write().unwrap().entry(node.clone()).or_insert(0) += x;
+        self.dirty.write().unwrap().insert(node);
+    }
+
+    pub fn value(&self) -> u64 {
+        self.data.read().unwrap().values().sum()
+    }
+
+    pub fn counts(&self) -> HashMap<String, u64> {
+        self.data.read().unwrap().clone()
+    }
+
+    // Current counts of the nodes incremented since the previous call
+    pub fn take_counts(&self) -> HashMap<String, u64> {
+        let dirty = std::mem::take(&mut *self.dirty.write().unwrap());
+        let data_guard = self.data.read().unwrap();
+        dirty
+            .into_iter()
+            .filter_map(|node| data_guard.get(&node).map(|&x| (node, x)))
+            .collect()
+    }
+
+    pub fn merge_counts(&self, other: HashMap<String, u64>) {
+        let mut data_guard = self.data.write().unwrap();
+        other.into_iter().for_each(|(node, x)| {
+            let y = data_guard.entry(node).or_insert(0);
+            *y = (*y).max(x);
+        });
+    }
+}
+
+impl CrdtTrait for GCounter {
+    type Op = CounterOp;
+    type State = HashMap<String, u64>;
+    type Value = u64;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("GCounter!");
+        GCounter {
+            node_id: node_id.to_owned(),
+            data: RwLock::new(HashMap::new()),
+            dirty: RwLock::new(HashSet::new()),
+        }
+    }
+
+    fn apply(&self, op: CounterOp) -> Result<OpOkPayload, ErrorPayload> {
+        match op {
+            CounterOp::Add(add_p) if add_p.delta >= 0 => {
+                self.increment(self.node_id.clone(), add_p.delta as u64);
+                Ok(OpOkPayload::AddOk)
+            }
+            CounterOp::Add(add_p) => Err(ErrorPayload::new(
+                ErrorCode::MalformedRequest,
+                format!("G-Counter cannot add {}", add_p.delta),
+            )),
+        }
+    }
+
+    fn data(&self) -> HashMap<String, u64> {
+        self.counts()
+    }
+
+    fn take_delta(&self) -> Option<HashMap<String, u64>> {
+        let counts = self.take_counts();
+        (!counts.is_empty()).then_some(counts)
+    }
+
+    fn read(&self) -> u64 {
+        self.value()
+    }
+
+    fn merge(&self, other: HashMap<String, u64>) {
+        self.merge_counts(other);
+    }
+}
@@ -1,17 +1,29 @@
-use crate::crdt::crdt::{CrdtData, CrdtElem, CrdtTrait};
-
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::sync::RwLock;
 
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::msg::{ElementPayload, OpOkPayload};
+use crate::protocol::ErrorPayload;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum GSetOp {
+    #[serde(rename = "add")]
+    Add(ElementPayload),
+}
 
 #[derive(Debug)]
 pub struct GSet {
     data: RwLock<HashSet<usize>>,
+    delta: RwLock<HashSet<usize>>,
 }
 
 impl GSet {
     fn add_element(&self, element: usize) {
-        self.data.write().unwrap().insert(element);
+        if self.data.write().unwrap().insert(element) {
+            self.delta.write().unwrap().insert(element);
+        }
     }
 
     fn merge_data(&self, other_data_guard: HashSet<usize>) {
@@ -20,40 +32,41 @@ impl GSet {
 }
 
 impl CrdtTrait for GSet {
-    fn new(_neighbors: &HashSet<String>) -> Self {
+    type Op = GSetOp;
+    type State = HashSet<usize>;
+    type Value = HashSet<usize>;
+
+    fn new(_node_id: &str, _neighbors: &HashSet<String>) -> Self {
         eprintln!("GSet!");
         GSet {
             data: RwLock::new(HashSet::new()),
+            delta: RwLock::new(HashSet::new()),
         }
     }
 
-    fn data(&self) -> CrdtData {
-        CrdtData::GSetData(self.data.read().unwrap().clone())
+    fn data(&self) -> HashSet<usize> {
+        self.data.read().unwrap().clone()
     }
 
-    fn add(&self, element: CrdtElem) {
-        match element {
-            CrdtElem::GSetElem(element) => {
-                self.add_element(element);
-            }
-            _ => {
-                panic!("Wrong element type");
+    fn take_delta(&self) -> Option<HashSet<usize>> {
+        let delta = std::mem::take(&mut *self.delta.write().unwrap());
+        (!delta.is_empty()).then_some(delta)
+    }
+
+    fn apply(&self, op: GSetOp) -> Result<OpOkPayload, ErrorPayload> {
+        match op {
+            GSetOp::Add(add_p) => {
+                self.add_element(add_p.element);
+                Ok(OpOkPayload::AddOk)
             }
         }
     }
 
-    fn read_json(&self) -> serde_json::Value {
-        serde_json::to_value(&*self.data.read().unwrap()).unwrap()
+    fn read(&self) -> HashSet<usize> {
+        self.data()
     }
 
-    fn merge(&self, other: CrdtData) {
-        match other {
-            CrdtData::GSetData(other) => {
-                self.merge_data(other);
-            }
-            _ => {
-                panic!("Wrong data type");
-            }
-        }
+    fn merge(&self, other: HashSet<usize>) {
+        self.merge_data(other);
     }
 }
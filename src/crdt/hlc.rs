@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Hybrid logical clock timestamp: wall clock in ms, a logical counter to
+// order events within the same ms or behind a peer's clock, and the node
+// to break ties. Ordered field by field.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Timestamp {
+    pub physical: u64,
+    pub logical: u64,
+    pub node: String,
+}
+
+#[derive(Debug, Default)]
+pub struct HybridClock {
+    // Highest (physical, logical) issued or observed
+    last: Mutex<(u64, u64)>,
+}
+
+fn wall_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl HybridClock {
+    // Greater than every timestamp issued or observed so far, even if the
+    // wall clock lags behind a peer's
+    pub fn now(&self, node: &str) -> Timestamp {
+        let mut last = self.last.lock().unwrap();
+        let physical = wall_clock();
+        *last = if physical > last.0 {
+            (physical, 0)
+        } else {
+            (last.0, last.1 + 1)
+        };
+        Timestamp {
+            physical: last.0,
+            logical: last.1,
+            node: node.to_owned(),
+        }
+    }
+
+    pub fn observe(&self, timestamp: &Timestamp) {
+        let mut last = self.last.lock().unwrap();
+        if (timestamp.physical, timestamp.logical) > *last {
+            *last = (timestamp.physical, timestamp.logical);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::{parse_key, CrdtTrait};
+use crate::crdt::hlc::{HybridClock, Timestamp};
+use crate::crdt::msg::OpOkPayload;
+use crate::protocol::{ErrorCode, ErrorPayload};
+
+// Value along with the time it was written at
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Versioned {
+    pub value: serde_json::Value,
+    pub timestamp: Timestamp,
+}
+
+impl Versioned {
+    pub fn new(value: serde_json::Value, timestamp: Timestamp) -> Self {
+        Versioned { value, timestamp }
+    }
+}
+
+// Keep the latest write, timestamps are unique so all replicas agree
+fn merge_versioned(current: &mut Option<Versioned>, other: Versioned) {
+    match current {
+        Some(c) if c.timestamp >= other.timestamp => (),
+        _ => *current = Some(other),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WritePayload {
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum LwwRegisterOp {
+    #[serde(rename = "write")]
+    Write(WritePayload),
+}
+
+#[derive(Debug)]
+pub struct LwwRegister {
+    node_id: String,
+    data: RwLock<Option<Versioned>>,
+    delta: RwLock<Option<Versioned>>,
+    clock: HybridClock,
+}
+
+impl CrdtTrait for LwwRegister {
+    type Op = LwwRegisterOp;
+    type State = Option<Versioned>;
+    type Value = serde_json::Value;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("LwwRegister!");
+        LwwRegister {
+            node_id: node_id.to_owned(),
+            data: RwLock::new(None),
+            delta: RwLock::new(None),
+            clock: HybridClock::default(),
+        }
+    }
+
+    fn apply(&self, op: LwwRegisterOp) -> Result<OpOkPayload, ErrorPayload> {
+        match op {
+            LwwRegisterOp::Write(write_p) => {
+                let versioned = Versioned::new(write_p.value, self.clock.now(&self.node_id));
+                merge_versioned(&mut self.delta.write().unwrap(), versioned.clone());
+                merge_versioned(&mut self.data.write().unwrap(), versioned);
+                Ok(OpOkPayload::WriteOk)
+            }
+        }
+    }
+
+    fn data(&self) -> Option<Versioned> {
+        self.data.read().unwrap().clone()
+    }
+
+    fn take_delta(&self) -> Option<Option<Versioned>> {
+        self.delta.write().unwrap().take().map(Some)
+    }
+
+    fn read(&self) -> serde_json::Value {
+        match &*self.data.read().unwrap() {
+            Some(versioned) => versioned.value.clone(),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    fn merge(&self, other: Option<Versioned>) {
+        if let Some(other) = other {
+            self.clock.observe(&other.timestamp);
+            merge_versioned(&mut self.data.write().unwrap(), other);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct LwwMapData(#[serde(with = "crate::crdt::wire::pairs")] pub HashMap<usize, Versioned>);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyWritePayload {
+    pub key: usize,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum LwwMapOp {
+    #[serde(rename = "write")]
+    Write(KeyWritePayload),
+}
+
+// One last-writer-wins register per key
+#[derive(Debug)]
+pub struct LwwMap {
+    node_id: String,
+    data: RwLock<HashMap<usize, Versioned>>,
+    delta: RwLock<HashMap<usize, Versioned>>,
+    clock: HybridClock,
+}
+
+impl LwwMap {
+    fn merge_entry(data: &mut HashMap<usize, Versioned>, key: usize, other: Versioned) {
+        let mut current = data.remove(&key);
+        merge_versioned(&mut current, other);
+        data.extend(current.map(|versioned| (key, versioned)));
+    }
+}
+
+impl CrdtTrait for LwwMap {
+    type Op = LwwMapOp;
+    type State = LwwMapData;
+    type Value = HashMap<String, serde_json::Value>;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("LwwMap!");
+        LwwMap {
+            node_id: node_id.to_owned(),
+            data: RwLock::new(HashMap::new()),
+            delta: RwLock::new(HashMap::new()),
+            clock: HybridClock::default(),
+        }
+    }
+
+    fn apply(&self, op: LwwMapOp) -> Result<OpOkPayload, ErrorPayload> {
+        match op {
+            LwwMapOp::Write(write_p) => {
+                let versioned = Versioned::new(write_p.value, self.clock.now(&self.node_id));
+                LwwMap::merge_entry(
+                    &mut self.delta.write().unwrap(),
+                    write_p.key,
+                    versioned.clone(),
+                );
+                LwwMap::merge_entry(&mut self.data.write().unwrap(), write_p.key, versioned);
+                Ok(OpOkPayload::WriteOk)
+            }
+        }
+    }
+
+    fn data(&self) -> LwwMapData {
+        LwwMapData(self.data.read().unwrap().clone())
+    }
+
+    fn take_delta(&self) -> Option<LwwMapData> {
+        let delta = std::mem::take(&mut *self.delta.write().unwrap());
+        (!delta.is_empty()).then_some(LwwMapData(delta))
+    }
+
+    // JSON object keys are strings
+    fn read(&self) -> HashMap<String, serde_json::Value> {
+        self.data
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.value.clone()))
+            .collect()
+    }
+
+    fn read_key(&self, key: &serde_json::Value) -> Result<serde_json::Value, ErrorPayload> {
+        let key: usize = parse_key(key)?;
+        match self.data.read().unwrap().get(&key) {
+            Some(versioned) => Ok(versioned.value.clone()),
+            None => Err(ErrorPayload::new(
+                ErrorCode::KeyDoesNotExist,
+                format!("Key {} does not exist", key),
+            )),
+        }
+    }
+
+    fn merge(&self, other: LwwMapData) {
+        let mut data_guard = self.data.write().unwrap();
+        other.0.into_iter().for_each(|(key, versioned)| {
+            self.clock.observe(&versioned.timestamp);
+            LwwMap::merge_entry(&mut data_guard, key, versioned);
+        });
+    }
+}
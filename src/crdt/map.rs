@@ -0,0 +1,131 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::RwLock;
+
+use crate::crdt::crdt::{parse_key, CrdtTrait};
+use crate::crdt::msg::OpOkPayload;
+use crate::protocol::{ErrorCode, ErrorPayload};
+
+// An operation of the inner CRDT, addressed to one key:
+// {"type": "add", "key": "a", "delta": 3}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyedOp<K, O> {
+    pub key: K,
+    #[serde(flatten)]
+    pub op: O,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(bound(
+    serialize = "K: Serialize, S: Serialize",
+    deserialize = "K: Deserialize<'de> + Eq + Hash, S: Deserialize<'de>"
+))]
+pub struct CrdtMapData<K, S>(#[serde(with = "crate::crdt::wire::pairs")] pub HashMap<K, S>);
+
+// One CRDT per key, created on first use: a key is merged with the same key
+// of the other replica only, so the map is as much a CRDT as its values.
+#[derive(Debug)]
+pub struct CrdtMap<K, C> {
+    node_id: String,
+    neighbors: HashSet<String>,
+    data: RwLock<HashMap<K, C>>,
+}
+
+impl<K, C> CrdtMap<K, C>
+where
+    K: Clone + Eq + Hash,
+    C: CrdtTrait,
+{
+    // Runs `f` on the CRDT at `key`, creating it if missing
+    fn with_entry<T>(&self, key: K, f: impl FnOnce(&C) -> T) -> T {
+        if let Some(crdt) = self.data.read().unwrap().get(&key) {
+            return f(crdt);
+        }
+        let mut data_guard = self.data.write().unwrap();
+        let crdt = data_guard
+            .entry(key)
+            .or_insert_with(|| C::new(&self.node_id, &self.neighbors));
+        f(crdt)
+    }
+}
+
+impl<K, C> CrdtTrait for CrdtMap<K, C>
+where
+    K: Clone + Eq + Hash + Display + std::fmt::Debug + Serialize + DeserializeOwned,
+    K: Send + Sync + 'static,
+    C: CrdtTrait,
+{
+    type Op = KeyedOp<K, C::Op>;
+    type State = CrdtMapData<K, C::State>;
+    // JSON object keys are strings
+    type Value = HashMap<String, C::Value>;
+
+    fn new(node_id: &str, neighbors: &HashSet<String>) -> Self {
+        eprintln!("CrdtMap!");
+        CrdtMap {
+            node_id: node_id.to_owned(),
+            neighbors: neighbors.clone(),
+            data: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn apply(&self, op: Self::Op) -> Result<OpOkPayload, ErrorPayload> {
+        let KeyedOp { key, op } = op;
+        self.with_entry(key, |crdt| crdt.apply(op))
+    }
+
+    fn data(&self) -> Self::State {
+        let data_guard = self.data.read().unwrap();
+        CrdtMapData(
+            data_guard
+                .iter()
+                .map(|(k, c)| (k.clone(), c.data()))
+                .collect(),
+        )
+    }
+
+    fn take_delta(&self) -> Option<Self::State> {
+        let data_guard = self.data.read().unwrap();
+        let delta: HashMap<K, C::State> = data_guard
+            .iter()
+            .filter_map(|(k, c)| c.take_delta().map(|d| (k.clone(), d)))
+            .collect();
+        (!delta.is_empty()).then_some(CrdtMapData(delta))
+    }
+
+    fn read(&self) -> Self::Value {
+        let data_guard = self.data.read().unwrap();
+        data_guard
+            .iter()
+            .map(|(k, c)| (k.to_string(), c.read()))
+            .collect()
+    }
+
+    fn read_key(&self, key: &serde_json::Value) -> Result<serde_json::Value, ErrorPayload> {
+        let key: K = parse_key(key)?;
+        match self.data.read().unwrap().get(&key) {
+            Some(crdt) => Ok(serde_json::to_value(crdt.read()).unwrap()),
+            None => Err(ErrorPayload::new(
+                ErrorCode::KeyDoesNotExist,
+                format!("Key {} does not exist", key),
+            )),
+        }
+    }
+
+    fn merge(&self, other: Self::State) {
+        other
+            .0
+            .into_iter()
+            .for_each(|(k, s)| self.with_entry(k, |crdt| crdt.merge(s)));
+    }
+
+    fn merge_delta(&self, delta: Self::State) {
+        delta
+            .0
+            .into_iter()
+            .for_each(|(k, d)| self.with_entry(k, |crdt| crdt.merge_delta(d)));
+    }
+}
@@ -1,6 +1,18 @@
+pub mod causal;
+#[allow(clippy::module_inception)]
 pub mod crdt;
+pub mod delta;
+pub mod gcounter;
 pub mod gset;
+pub mod hlc;
+pub mod lww;
+pub mod map;
 pub mod msg;
 pub mod node;
+pub mod op_orset;
+pub mod orset;
 pub mod pncounter;
+pub mod rga;
 pub mod tasks;
+pub mod vclock;
+pub mod wire;
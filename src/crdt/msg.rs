@@ -1,147 +1,175 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::io::{self, Write};
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use super::crdt::CrdtData;
+use crate::crdt::causal::CausalOp;
+use crate::crdt::vclock::VClock;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "snake_case")]
-pub struct Message<P: PayloadTrait> {
-    pub src: String,
-    pub dest: String,
-    pub body: Body<P>,
+pub struct ElementPayload {
+    pub element: usize,
 }
 
-impl<P> Message<P>
-where
-    P: PayloadTrait + Serialize,
-{
-    pub fn new(dest: String, body: Body<P>, src: String) -> Self {
-        Message { src, dest, body }
+impl ElementPayload {
+    pub fn new(element: usize) -> Self {
+        ElementPayload { element }
     }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeltaPayload {
+    pub delta: i64,
+}
 
-    pub fn send(&self) {
-        let mut stdout = io::stdout().lock();
-        serde_json::to_writer(&mut stdout, &self).unwrap();
-        stdout.write_all(b"\n").unwrap();
-        stdout.flush().unwrap();
+impl DeltaPayload {
+    pub fn new(delta: i64) -> Self {
+        DeltaPayload { delta }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitPayload {
-    pub node_id: String,
-    pub node_ids: HashSet<String>,
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReadPayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct AddPayload {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub element: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub delta: Option<i64>,
+pub struct ReadOkPayload {
+    value: serde_json::Value,
 }
 
-impl AddPayload {
-    pub fn new(element: Option<usize>, delta: Option<i64>) -> Self {
-        AddPayload { element, delta }
+impl ReadOkPayload {
+    pub fn new(value: serde_json::Value) -> Self {
+        ReadOkPayload { value }
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitOkPayload {
-    in_reply_to: usize,
+pub struct ReplicatePayload<S> {
+    pub data: S,
 }
 
-impl InitOkPayload {
-    pub fn new(in_reply_to: usize) -> Self {
-        InitOkPayload { in_reply_to }
+impl<S> ReplicatePayload<S> {
+    pub fn new(data: S) -> Self {
+        ReplicatePayload { data }
     }
 }
 
+// Local changes a peer has not acked yet, oldest first
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ReadOkPayload {
-    in_reply_to: usize,
-    value: serde_json::Value,
+pub struct ReplicateDeltaPayload<S> {
+    pub deltas: Vec<S>,
 }
 
-impl ReadOkPayload {
-    pub fn new(in_reply_to: usize, value: serde_json::Value) -> Self {
-        ReadOkPayload { in_reply_to, value }
+impl<S> ReplicateDeltaPayload<S> {
+    pub fn new(deltas: Vec<S>) -> Self {
+        ReplicateDeltaPayload { deltas }
     }
 }
 
+// Operations a peer has not acked yet, in causal order
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct AddOkPayload {
-    pub in_reply_to: usize,
+pub struct ReplicateOpsPayload<S> {
+    pub ops: Vec<CausalOp<S>>,
 }
 
-impl AddOkPayload {
-    pub fn new(in_reply_to: usize) -> Self {
-        AddOkPayload { in_reply_to }
+impl<S> ReplicateOpsPayload<S> {
+    pub fn new(ops: Vec<CausalOp<S>>) -> Self {
+        ReplicateOpsPayload { ops }
     }
 }
 
+// Everything the peer delivered, whoever it came from
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ReplicatePayload {
-    pub data: CrdtData,
+pub struct ReplicateOpsOkPayload {
+    pub delivered: VClock,
 }
 
-impl ReplicatePayload {
-    pub fn new(data: CrdtData) -> Self {
-        ReplicatePayload { data }
+impl ReplicateOpsOkPayload {
+    pub fn new(delivered: VClock) -> Self {
+        ReplicateOpsOkPayload { delivered }
     }
 }
 
-pub trait PayloadTrait {}
-
+// Requests every CRDT node serves, whatever its type
 #[derive(Debug, Clone, Deserialize, Serialize)]
-//#[serde(untagged)]
 #[serde(tag = "type")]
-pub enum ReqPayload {
-    #[serde(rename = "init")]
-    Init(InitPayload),
+pub enum NodeReqPayload<S> {
     #[serde(rename = "read")]
-    Read,
-    #[serde(rename = "add")]
-    Add(AddPayload),
+    Read(ReadPayload),
     #[serde(rename = "replicate")]
-    Replicate(ReplicatePayload),
+    Replicate(ReplicatePayload<S>),
+    #[serde(rename = "replicate_delta")]
+    ReplicateDelta(ReplicateDeltaPayload<S>),
+    #[serde(rename = "replicate_ops")]
+    ReplicateOps(ReplicateOpsPayload<S>),
+}
+
+// Either a node request or an operation of the node's CRDT `O`
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ReqPayload<O, S> {
+    Node(NodeReqPayload<S>),
+    Op(O),
 }
 
-impl PayloadTrait for ReqPayload {}
+// Dispatch on `type` rather than trying both, so that parse errors tell
+// what is actually wrong with the request
+impl<'de, O, S> Deserialize<'de> for ReqPayload<O, S>
+where
+    O: DeserializeOwned,
+    S: DeserializeOwned,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some("read" | "replicate" | "replicate_delta" | "replicate_ops") => {
+                serde_json::from_value(value)
+                    .map(ReqPayload::Node)
+                    .map_err(de::Error::custom)
+            }
+            _ => serde_json::from_value(value)
+                .map(ReqPayload::Op)
+                .map_err(de::Error::custom),
+        }
+    }
+}
 
 pub trait SendTrait {}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 //#[serde(untagged)]
 #[serde(tag = "type")]
-pub enum SendPayload {
-    #[serde(rename = "init_ok")]
-    InitOk(InitOkPayload),
+pub enum SendPayload<S> {
     #[serde(rename = "read_ok")]
     ReadOk(ReadOkPayload),
     #[serde(rename = "replicate")]
-    Replicate(ReplicatePayload),
-    #[serde(rename = "add_ok")]
-    AddOk(AddOkPayload),
+    Replicate(ReplicatePayload<S>),
+    #[serde(rename = "replicate_delta")]
+    ReplicateDelta(ReplicateDeltaPayload<S>),
+    #[serde(rename = "replicate_ops")]
+    ReplicateOps(ReplicateOpsPayload<S>),
+    #[serde(rename = "replicate_ok")]
+    ReplicateOk,
+    #[serde(rename = "replicate_ops_ok")]
+    ReplicateOpsOk(ReplicateOpsOkPayload),
 }
 
-impl SendTrait for SendPayload {}
-impl PayloadTrait for SendPayload {}
+impl<S> SendTrait for SendPayload<S> {}
 
+// Replies to CRDT operations
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Body<P: PayloadTrait> {
-    #[serde(flatten)]
-    pub payload: P,
-    pub msg_id: usize,
-}
-
-impl<P> Body<P>
-where
-    P: PayloadTrait,
-{
-    pub fn new(payload: P, msg_id: usize) -> Self {
-        Body { payload, msg_id }
-    }
-}
+#[serde(tag = "type")]
+pub enum OpOkPayload {
+    #[serde(rename = "add_ok")]
+    AddOk,
+    #[serde(rename = "remove_ok")]
+    RemoveOk,
+    #[serde(rename = "write_ok")]
+    WriteOk,
+    #[serde(rename = "insert_ok")]
+    InsertOk,
+    #[serde(rename = "delete_ok")]
+    DeleteOk,
+}
+
+impl SendTrait for OpOkPayload {}
@@ -1,94 +1,130 @@
-use crate::crdt::crdt::{CrdtElem, CrdtTrait};
-use crate::crdt::msg::{AddOkPayload, Body, InitOkPayload, Message};
+use crate::crdt::causal::CausalLog;
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::delta::DeltaLog;
+use crate::crdt::tasks::{compact_deltas, log_metrics, replicate_deltas, replicate_ops};
+use crate::protocol::{ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::handler::Handler;
+use crate::runtime::task::Task;
 
-use super::msg::{PayloadTrait, ReadOkPayload, ReqPayload, SendPayload};
-use serde::Serialize;
-use std::collections::HashSet;
+use super::msg::{
+    NodeReqPayload, ReadOkPayload, ReadPayload, ReplicateOpsOkPayload, ReqPayload, SendPayload,
+};
+use std::collections::{HashMap, HashSet};
 
-use std::io::{self, Write};
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 #[derive(Debug)]
 pub struct Node<C>
 where
     C: CrdtTrait,
 {
-    pub node_id: String,
-    next_msg_id: RwLock<usize>,
+    pub ctx: Arc<Context>,
     pub crdt: C,
     pub neighbors: RwLock<HashSet<String>>,
+    pub deltas: RwLock<DeltaLog<C::State>>,
+    // Number of deltas each peer acked
+    pub acked: Arc<RwLock<HashMap<String, u64>>>,
+    // Peers with a replication batch not acked yet
+    pub in_flight: Arc<RwLock<HashSet<String>>>,
+    // Causal CRDTs only: deltas stamped with vector clocks, delivered in
+    // causal order, instead of `deltas`
+    pub causal: Arc<RwLock<CausalLog<C::State>>>,
 }
 
-impl<C: CrdtTrait> Node<C> {
-    pub fn new(node_id: String, neighbors: HashSet<String>) -> Self {
-        Node {
-            node_id,
-            next_msg_id: RwLock::new(0),
-            crdt: C::new(&neighbors),
-            neighbors: RwLock::new(neighbors),
+impl<C> Node<C>
+where
+    C: CrdtTrait,
+{
+    fn handle_node(
+        &self,
+        payload: NodeReqPayload<C::State>,
+    ) -> Result<SendPayload<C::State>, ErrorPayload> {
+        match payload {
+            NodeReqPayload::Replicate(replicate_p) => {
+                self.crdt.merge(replicate_p.data);
+                Ok(SendPayload::ReplicateOk)
+            }
+            NodeReqPayload::ReplicateDelta(replicate_p) => {
+                replicate_p
+                    .deltas
+                    .into_iter()
+                    .for_each(|delta| self.crdt.merge_delta(delta));
+                Ok(SendPayload::ReplicateOk)
+            }
+            NodeReqPayload::ReplicateOps(replicate_p) => {
+                // Held while merging, so that a local delta stamped
+                // meanwhile depends on every effect already applied
+                let mut causal = self.causal.write().unwrap();
+                for op in replicate_p.ops {
+                    causal
+                        .receive(op)
+                        .into_iter()
+                        .for_each(|delta| self.crdt.merge_delta(delta));
+                }
+                let delivered = causal.delivered().clone();
+                Ok(SendPayload::ReplicateOpsOk(ReplicateOpsOkPayload::new(
+                    delivered,
+                )))
+            }
+            NodeReqPayload::Read(ReadPayload { key: None }) => {
+                let value = serde_json::to_value(self.crdt.read()).unwrap();
+                Ok(SendPayload::ReadOk(ReadOkPayload::new(value)))
+            }
+            NodeReqPayload::Read(ReadPayload { key: Some(key) }) => {
+                let value = self.crdt.read_key(&key)?;
+                Ok(SendPayload::ReadOk(ReadOkPayload::new(value)))
+            }
         }
     }
+}
 
-    pub fn log<M>(&self, msg: &M)
-    where
-        M: Serialize,
-    {
-        let mut stderr = io::stderr();
-        serde_json::to_writer(&stderr, &msg).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-    }
+impl<C> Handler for Node<C>
+where
+    C: CrdtTrait,
+{
+    type Payload = ReqPayload<C::Op, C::State>;
 
-    pub fn build_body<P>(&self, payload: P) -> Body<P>
-    where
-        P: PayloadTrait,
-    {
-        let body = Body::new(payload, self.next_msg_id.read().unwrap().clone());
-        let mut nxt_msg_id_guard = self.next_msg_id.write().unwrap();
-        *nxt_msg_id_guard += 1;
-        body
+    fn init(ctx: Arc<Context>) -> Self {
+        let neighbors = ctx.neighbors();
+        Node {
+            crdt: C::new(&ctx.node_id, &neighbors),
+            neighbors: RwLock::new(neighbors),
+            deltas: RwLock::new(DeltaLog::default()),
+            acked: Arc::new(RwLock::new(HashMap::new())),
+            in_flight: Arc::new(RwLock::new(HashSet::new())),
+            causal: Arc::new(RwLock::new(CausalLog::default())),
+            ctx,
+        }
     }
 
-    pub fn handle_msg(&self, request: Message<ReqPayload>) -> Result<(), ()> {
-        self.log(&format!("Received {:?}", request));
+    fn handle(&self, request: Message<Self::Payload>) -> Result<(), ErrorPayload> {
+        self.ctx.log(&format!("Received {:?}", request));
 
-        let req_msg_id = request.body.msg_id;
-
-        // effect from request
         match &request.body.payload {
-            ReqPayload::Add(add_p) => {
-                add_p.element.map(|e| self.crdt.add(CrdtElem::GSetElem(e)));
-                add_p.delta.map(|d| {
-                    self.crdt
-                        .add(CrdtElem::PNCounterDelta(self.node_id.clone(), d))
-                });
-            }
-            ReqPayload::Replicate(replicate_p) => {
-                self.crdt.merge(replicate_p.data.clone());
+            ReqPayload::Node(payload) => {
+                let reply_payload = self.handle_node(payload.clone())?;
+                self.ctx.reply(&request, reply_payload);
             }
-            _ => (),
-        };
-
-        // send response
-        let reply_payload_opt = match request.body.payload {
-            ReqPayload::Init(_) => Some(SendPayload::InitOk(InitOkPayload::new(req_msg_id))),
-            ReqPayload::Add(_) => Some(SendPayload::AddOk(AddOkPayload::new(req_msg_id))),
-            ReqPayload::Read => {
-                self.log(&format!("Request READ {:?}", request.src));
-                let crdt_json = self.crdt.read_json();
-                Some(SendPayload::ReadOk(ReadOkPayload::new(
-                    req_msg_id, crdt_json,
-                )))
+            ReqPayload::Op(op) => {
+                let reply_payload = self.crdt.apply(op.clone())?;
+                self.ctx.reply(&request, reply_payload);
             }
-            _ => None,
         };
 
-        if let Some(payload) = reply_payload_opt {
-            let body = self.build_body(payload);
-            self.log(&format!("Send {:?}", body));
-            Message::new(request.src.to_owned(), body, self.node_id.clone()).send();
-        }
-
         Ok(())
     }
+
+    fn tasks() -> Vec<Task<Self>> {
+        let replicate = if C::CAUSAL {
+            replicate_ops
+        } else {
+            replicate_deltas
+        };
+        vec![
+            Task::new("replicate", 10, replicate),
+            Task::new("compact", 100, compact_deltas),
+            Task::new("metrics", 1000, log_metrics).with_jitter(0.1),
+        ]
+    }
 }
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::OpCrdtTrait;
+use crate::crdt::msg::OpOkPayload;
+use crate::crdt::orset::{Dot, ORSetOp};
+use crate::protocol::ErrorPayload;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum ORSetEffect {
+    #[serde(rename = "add")]
+    Add { element: usize, dot: Dot },
+    // Drops the dots observed at the origin, causal delivery guarantees
+    // their adds were applied first
+    #[serde(rename = "remove")]
+    Remove { element: usize, dots: HashSet<Dot> },
+}
+
+// Operation-based observed-remove set, add-wins. Same semantics as
+// `orset::ORSet` without any causal context: causal delivery makes it
+// unnecessary.
+#[derive(Debug)]
+pub struct OpORSet {
+    node_id: String,
+    counter: RwLock<u64>,
+    entries: RwLock<HashMap<usize, HashSet<Dot>>>,
+}
+
+impl OpCrdtTrait for OpORSet {
+    type Op = ORSetOp;
+    type Effect = ORSetEffect;
+    type Value = HashSet<usize>;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("OpORSet!");
+        OpORSet {
+            node_id: node_id.to_owned(),
+            counter: RwLock::new(0),
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn prepare(&self, op: ORSetOp) -> Result<(OpOkPayload, Option<ORSetEffect>), ErrorPayload> {
+        match op {
+            ORSetOp::Add(add_p) => {
+                let mut counter = self.counter.write().unwrap();
+                *counter += 1;
+                let effect = ORSetEffect::Add {
+                    element: add_p.element,
+                    dot: (self.node_id.clone(), *counter),
+                };
+                Ok((OpOkPayload::AddOk, Some(effect)))
+            }
+            ORSetOp::Remove(remove_p) => {
+                let effect = self
+                    .entries
+                    .read()
+                    .unwrap()
+                    .get(&remove_p.element)
+                    .map(|dots| ORSetEffect::Remove {
+                        element: remove_p.element,
+                        dots: dots.clone(),
+                    });
+                Ok((OpOkPayload::RemoveOk, effect))
+            }
+        }
+    }
+
+    fn effect(&self, effect: ORSetEffect) {
+        let mut entries = self.entries.write().unwrap();
+        match effect {
+            ORSetEffect::Add { element, dot } => {
+                entries.entry(element).or_default().insert(dot);
+            }
+            ORSetEffect::Remove { element, dots } => {
+                if let Some(current) = entries.get_mut(&element) {
+                    current.retain(|dot| !dots.contains(dot));
+                    if current.is_empty() {
+                        entries.remove(&element);
+                    }
+                }
+            }
+        }
+    }
+
+    fn read(&self) -> HashSet<usize> {
+        self.entries.read().unwrap().keys().copied().collect()
+    }
+}
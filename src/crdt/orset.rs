@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::msg::{ElementPayload, OpOkPayload};
+use crate::protocol::ErrorPayload;
+
+// Unique tag of an add: the node it happened on and its counter there
+pub type Dot = (String, u64);
+
+// Observed-remove set, add-wins. Each add is tagged with a new dot, a
+// remove drops the dots it has observed. The causal context (every dot
+// seen: a version vector, plus the dots past it in the cloud) tells a
+// removed dot from one not received yet, so no tombstone is kept. Deltas
+// are ORSetData too, their context only holding the dots they touch.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ORSetData {
+    #[serde(with = "crate::crdt::wire::pairs")]
+    pub entries: HashMap<usize, HashSet<Dot>>,
+    pub context: HashMap<String, u64>,
+    #[serde(default)]
+    pub cloud: HashSet<Dot>,
+}
+
+impl ORSetData {
+    fn seen(&self, dot: &Dot) -> bool {
+        self.context.get(&dot.0).is_some_and(|&c| dot.1 <= c) || self.cloud.contains(dot)
+    }
+
+    // Returns the delta of the add
+    fn add(&mut self, node: String, element: usize) -> ORSetData {
+        let counter = self.context.entry(node.clone()).or_insert(0);
+        *counter += 1;
+        let dot = (node, *counter);
+
+        // The new dot supersedes the ones this replica observed
+        let mut cloud = self
+            .entries
+            .insert(element, HashSet::from([dot.clone()]))
+            .unwrap_or_default();
+        cloud.insert(dot.clone());
+
+        ORSetData {
+            entries: HashMap::from([(element, HashSet::from([dot]))]),
+            context: HashMap::new(),
+            cloud,
+        }
+    }
+
+    // Returns the delta of the remove
+    fn remove(&mut self, element: usize) -> ORSetData {
+        ORSetData {
+            cloud: self.entries.remove(&element).unwrap_or_default(),
+            ..ORSetData::default()
+        }
+    }
+
+    fn merge(&mut self, mut other: ORSetData) {
+        let elements: HashSet<usize> = self
+            .entries
+            .keys()
+            .chain(other.entries.keys())
+            .copied()
+            .collect();
+        for element in elements {
+            let ours = self.entries.remove(&element).unwrap_or_default();
+            let theirs = other.entries.remove(&element).unwrap_or_default();
+
+            // A dot survives if both sides have it, or if the side missing
+            // it never saw it, rather than removed it
+            let dots: HashSet<Dot> = ours
+                .iter()
+                .filter(|&dot| theirs.contains(dot) || !other.seen(dot))
+                .chain(theirs.iter().filter(|&dot| !self.seen(dot)))
+                .cloned()
+                .collect();
+
+            if !dots.is_empty() {
+                self.entries.insert(element, dots);
+            }
+        }
+
+        other.context.into_iter().for_each(|(node, c)| {
+            let counter = self.context.entry(node).or_insert(0);
+            *counter = (*counter).max(c);
+        });
+        self.cloud.extend(other.cloud);
+        self.compact();
+    }
+
+    // Fold the cloud dots that became contiguous into the version vector
+    fn compact(&mut self) {
+        let mut cloud: Vec<Dot> = self.cloud.drain().collect();
+        cloud.sort();
+        for (node, c) in cloud {
+            let counter = self.context.entry(node.clone()).or_insert(0);
+            if c == *counter + 1 {
+                *counter = c;
+            } else if c > *counter {
+                self.cloud.insert((node, c));
+            }
+        }
+    }
+
+    pub fn elements(&self) -> HashSet<usize> {
+        self.entries.keys().copied().collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum ORSetOp {
+    #[serde(rename = "add")]
+    Add(ElementPayload),
+    #[serde(rename = "remove")]
+    Remove(ElementPayload),
+}
+
+#[derive(Debug)]
+pub struct ORSet {
+    node_id: String,
+    data: RwLock<ORSetData>,
+    delta: RwLock<Option<ORSetData>>,
+}
+
+impl ORSet {
+    fn push_delta(&self, delta: ORSetData) {
+        let mut delta_guard = self.delta.write().unwrap();
+        match &mut *delta_guard {
+            Some(buffered) => buffered.merge(delta),
+            None => *delta_guard = Some(delta),
+        }
+    }
+}
+
+impl CrdtTrait for ORSet {
+    type Op = ORSetOp;
+    type State = ORSetData;
+    type Value = HashSet<usize>;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("ORSet!");
+        ORSet {
+            node_id: node_id.to_owned(),
+            data: RwLock::new(ORSetData::default()),
+            delta: RwLock::new(None),
+        }
+    }
+
+    fn apply(&self, op: ORSetOp) -> Result<OpOkPayload, ErrorPayload> {
+        let (delta, ok) = match op {
+            ORSetOp::Add(add_p) => (
+                self.data
+                    .write()
+                    .unwrap()
+                    .add(self.node_id.clone(), add_p.element),
+                OpOkPayload::AddOk,
+            ),
+            ORSetOp::Remove(remove_p) => (
+                self.data.write().unwrap().remove(remove_p.element),
+                OpOkPayload::RemoveOk,
+            ),
+        };
+        self.push_delta(delta);
+        Ok(ok)
+    }
+
+    fn data(&self) -> ORSetData {
+        self.data.read().unwrap().clone()
+    }
+
+    fn take_delta(&self) -> Option<ORSetData> {
+        self.delta.write().unwrap().take()
+    }
+
+    fn read(&self) -> HashSet<usize> {
+        self.data.read().unwrap().elements()
+    }
+
+    fn merge(&self, other: ORSetData) {
+        self.data.write().unwrap().merge(other);
+    }
+}
@@ -1,110 +1,83 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::sync::RwLock;
-use std::thread::{self};
 
-use crate::crdt::crdt::{CrdtData, CrdtElem, CrdtTrait};
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::gcounter::{CounterOp, GCounter};
+use crate::crdt::msg::OpOkPayload;
+use crate::protocol::ErrorPayload;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct PNCounter {
-    incr: RwLock<HashMap<String, i64>>,
-    decr: RwLock<HashMap<String, i64>>,
-}
-
-fn clone(data: &RwLock<HashMap<String, i64>>) -> HashMap<String, i64> {
-    data.read().unwrap().clone()
-}
-
-fn sum(data: &RwLock<HashMap<String, i64>>) -> i64 {
-    data.read().unwrap().values().sum()
+// Increments and decrements per node
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PNCounterData {
+    pub incr: HashMap<String, u64>,
+    pub decr: HashMap<String, u64>,
 }
 
-fn add_x(data: &RwLock<HashMap<String, i64>>, node: String, x: i64) {
-    let mut data_guard = data.write().unwrap();
-    if let Some(y) = data_guard.get_mut(&node) {
-        *y += x;
-    } else {
-        data_guard.insert(node.clone(), x);
-    }
-}
-
-fn merge(data: &RwLock<HashMap<String, i64>>, other: HashMap<String, i64>) {
-    let mut data_guard = data.write().unwrap();
-    other.iter().for_each(|(node, x)| {
-        if let Some(y) = data_guard.get_mut(*&node) {
-            if (*y).abs() < (*x).abs() {
-                *y = *x;
-            }
-        } else {
-            data_guard.insert(node.clone(), *x);
-        }
-    });
+// Increments and decrements are counted apart on two grow-only counters,
+// so that merging is the per-node max of each: a true lattice join, no
+// matter the order or the number of times states are exchanged.
+#[derive(Debug)]
+pub struct PNCounter {
+    node_id: String,
+    incr: GCounter,
+    decr: GCounter,
 }
 
 impl PNCounter {
     fn add_x(&self, node: String, x: i64) {
         if x >= 0 {
-            add_x(&self.incr, node, x);
+            self.incr.increment(node, x as u64);
         } else {
-            add_x(&self.decr, node, x);
+            self.decr.increment(node, x.unsigned_abs());
         }
     }
 
-    fn merge_data(&self, other: (HashMap<String, i64>, HashMap<String, i64>)) {
-        let (other_incr, other_decr) = other;
-
-        thread::scope(|_s| {
-            merge(&self.incr, other_incr);
-        });
-        thread::scope(|_s| {
-            merge(&self.decr, other_decr);
-        });
+    pub fn value(&self) -> i64 {
+        self.incr.value() as i64 - self.decr.value() as i64
     }
 }
 
 impl CrdtTrait for PNCounter {
-    fn new(neighbors: &HashSet<String>) -> Self {
-        let map: HashMap<String, i64> = neighbors
-            .into_iter()
-            .map(|node| (node.clone(), 0))
-            .collect();
+    type Op = CounterOp;
+    type State = PNCounterData;
+    type Value = i64;
+
+    fn new(node_id: &str, neighbors: &HashSet<String>) -> Self {
         eprintln!("PNCounter!");
         PNCounter {
-            incr: RwLock::new(map.clone()),
-            decr: RwLock::new(map),
+            node_id: node_id.to_owned(),
+            incr: GCounter::new(node_id, neighbors),
+            decr: GCounter::new(node_id, neighbors),
         }
     }
 
-    fn add(&self, element: CrdtElem) {
-        match element {
-            CrdtElem::PNCounterDelta(node, x) => {
-                self.add_x(node, x);
-            }
-            _ => {
-                panic!("Wrong element type");
+    fn apply(&self, op: CounterOp) -> Result<OpOkPayload, ErrorPayload> {
+        match op {
+            CounterOp::Add(add_p) => {
+                self.add_x(self.node_id.clone(), add_p.delta);
+                Ok(OpOkPayload::AddOk)
             }
         }
     }
 
-    fn data(&self) -> CrdtData {
-        CrdtData::PNCounterData((clone(&self.incr), clone(&self.decr)))
+    fn data(&self) -> PNCounterData {
+        PNCounterData {
+            incr: self.incr.counts(),
+            decr: self.decr.counts(),
+        }
     }
 
-    fn read_json(&self) -> serde_json::Value {
-        let mut sum_int = thread::scope(|_s| sum(&self.incr));
-        sum_int += thread::scope(|_s| sum(&self.decr));
+    fn take_delta(&self) -> Option<PNCounterData> {
+        let (incr, decr) = (self.incr.take_counts(), self.decr.take_counts());
+        (!incr.is_empty() || !decr.is_empty()).then_some(PNCounterData { incr, decr })
+    }
 
-        return serde_json::to_value(&sum_int).unwrap();
+    fn read(&self) -> i64 {
+        self.value()
     }
 
-    fn merge(&self, other: CrdtData) {
-        match other {
-            CrdtData::PNCounterData(other) => {
-                self.merge_data(other);
-            }
-            _ => {
-                panic!("Wrong data type");
-            }
-        }
+    fn merge(&self, other: PNCounterData) {
+        self.incr.merge_counts(other.incr);
+        self.decr.merge_counts(other.decr);
     }
 }
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::crdt::crdt::CrdtTrait;
+use crate::crdt::msg::OpOkPayload;
+use crate::protocol::{ErrorCode, ErrorPayload};
+
+// Unique position of an element: a Lamport counter and the node it was
+// inserted on. Later inserts compare greater.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct ElemId {
+    pub counter: u64,
+    pub node: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Insert {
+    // Element inserted right after, None for the head of the sequence
+    pub after: Option<ElemId>,
+    pub value: serde_json::Value,
+}
+
+// Replicated growable array. Elements form a tree, each hanging off the
+// one it was inserted after; siblings are ordered newest first, so an
+// insert lands right after its anchor, and concurrent inserts at the same
+// place end up in the same order on every replica. Deleted elements stay
+// as tombstones, later inserts may be anchored on them. Deltas are
+// RgaData too.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RgaData {
+    #[serde(with = "crate::crdt::wire::pairs")]
+    pub inserts: HashMap<ElemId, Insert>,
+    pub deleted: HashSet<ElemId>,
+}
+
+impl RgaData {
+    fn next_id(&self, node: String) -> ElemId {
+        let counter = self.inserts.keys().map(|id| id.counter).max().unwrap_or(0);
+        ElemId {
+            counter: counter + 1,
+            node,
+        }
+    }
+
+    // Every element in sequence order, tombstones included. An element
+    // whose anchor did not arrive yet is left out until it does.
+    fn order(&self) -> Vec<&ElemId> {
+        let mut children: HashMap<Option<&ElemId>, Vec<&ElemId>> = HashMap::new();
+        self.inserts.iter().for_each(|(id, insert)| {
+            children.entry(insert.after.as_ref()).or_default().push(id);
+        });
+
+        children.values_mut().for_each(|ids| ids.sort());
+
+        // Depth first, newest sibling first
+        let mut order = Vec::with_capacity(self.inserts.len());
+        let mut stack: Vec<&ElemId> = children.get(&None).cloned().unwrap_or_default();
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(children.get(&Some(id)).into_iter().flatten());
+        }
+        order
+    }
+
+    fn visible(&self) -> Vec<&ElemId> {
+        self.order()
+            .into_iter()
+            .filter(|id| !self.deleted.contains(*id))
+            .collect()
+    }
+
+    fn out_of_range(index: usize, len: usize) -> ErrorPayload {
+        ErrorPayload::new(
+            ErrorCode::PreconditionFailed,
+            format!("Index {} out of range, length is {}", index, len),
+        )
+    }
+
+    // Returns the delta of the insert
+    fn insert(
+        &mut self,
+        node: String,
+        index: usize,
+        value: serde_json::Value,
+    ) -> Result<RgaData, ErrorPayload> {
+        let visible = self.visible();
+        if index > visible.len() {
+            return Err(RgaData::out_of_range(index, visible.len()));
+        }
+        let after = index.checked_sub(1).map(|i| visible[i].clone());
+
+        let id = self.next_id(node);
+        let insert = Insert { after, value };
+        self.inserts.insert(id.clone(), insert.clone());
+        Ok(RgaData {
+            inserts: HashMap::from([(id, insert)]),
+            deleted: HashSet::new(),
+        })
+    }
+
+    // Returns the delta of the delete
+    fn delete(&mut self, index: usize) -> Result<RgaData, ErrorPayload> {
+        let visible = self.visible();
+        let id = match visible.get(index) {
+            Some(&id) => id.clone(),
+            None => return Err(RgaData::out_of_range(index, visible.len())),
+        };
+        self.deleted.insert(id.clone());
+        Ok(RgaData {
+            inserts: HashMap::new(),
+            deleted: HashSet::from([id]),
+        })
+    }
+
+    fn merge(&mut self, other: RgaData) {
+        self.inserts.extend(other.inserts);
+        self.deleted.extend(other.deleted);
+    }
+
+    pub fn values(&self) -> Vec<serde_json::Value> {
+        self.visible()
+            .into_iter()
+            .map(|id| self.inserts[id].value.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InsertPayload {
+    pub index: usize,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeletePayload {
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum RgaOp {
+    #[serde(rename = "insert")]
+    Insert(InsertPayload),
+    #[serde(rename = "delete")]
+    Delete(DeletePayload),
+}
+
+#[derive(Debug)]
+pub struct Rga {
+    node_id: String,
+    data: RwLock<RgaData>,
+    delta: RwLock<Option<RgaData>>,
+}
+
+impl Rga {
+    fn push_delta(&self, delta: RgaData) {
+        let mut delta_guard = self.delta.write().unwrap();
+        match &mut *delta_guard {
+            Some(buffered) => buffered.merge(delta),
+            None => *delta_guard = Some(delta),
+        }
+    }
+}
+
+impl CrdtTrait for Rga {
+    type Op = RgaOp;
+    type State = RgaData;
+    type Value = Vec<serde_json::Value>;
+
+    fn new(node_id: &str, _neighbors: &HashSet<String>) -> Self {
+        eprintln!("Rga!");
+        Rga {
+            node_id: node_id.to_owned(),
+            data: RwLock::new(RgaData::default()),
+            delta: RwLock::new(None),
+        }
+    }
+
+    fn apply(&self, op: RgaOp) -> Result<OpOkPayload, ErrorPayload> {
+        let (delta, ok) = match op {
+            RgaOp::Insert(insert_p) => (
+                self.data.write().unwrap().insert(
+                    self.node_id.clone(),
+                    insert_p.index,
+                    insert_p.value,
+                )?,
+                OpOkPayload::InsertOk,
+            ),
+            RgaOp::Delete(delete_p) => (
+                self.data.write().unwrap().delete(delete_p.index)?,
+                OpOkPayload::DeleteOk,
+            ),
+        };
+        self.push_delta(delta);
+        Ok(ok)
+    }
+
+    fn data(&self) -> RgaData {
+        self.data.read().unwrap().clone()
+    }
+
+    fn take_delta(&self) -> Option<RgaData> {
+        self.delta.write().unwrap().take()
+    }
+
+    fn read(&self) -> Vec<serde_json::Value> {
+        self.data.read().unwrap().values()
+    }
+
+    fn merge(&self, other: RgaData) {
+        self.data.write().unwrap().merge(other);
+    }
+}
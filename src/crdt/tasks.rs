@@ -1,31 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::crdt::crdt::CrdtTrait;
-use crate::crdt::msg::{Message, ReplicatePayload, SendPayload};
+use crate::crdt::msg::{ReplicateDeltaPayload, ReplicateOpsPayload, ReplicatePayload, SendPayload};
 use crate::crdt::node::Node;
-use std::fmt;
+use crate::protocol::Message;
+use crate::runtime::rpc::RpcOptions;
+
+const REPLICATE_TIMEOUT: Duration = Duration::from_millis(200);
+// Operations per replicate_ops
+const MAX_OPS: usize = 1024;
+
+pub fn replicate_deltas<C: CrdtTrait>(node: &Node<C>) {
+    let mut log = node.deltas.write().unwrap();
+    if let Some(delta) = node.crdt.take_delta() {
+        log.push(delta);
+    }
+    let head = log.head();
 
-pub struct Task<C: CrdtTrait> {
-    pub callback: fn(&Node<C>),
-    pub millisec: u64,
+    // Send every neighbor what it has not acked yet, one batch at a time:
+    // deltas if still logged, the full state otherwise
+    let neighbors = node.neighbors.read().unwrap().clone();
+    let acked = node.acked.read().unwrap().clone();
+    neighbors.iter().for_each(|n| {
+        let peer_acked = acked.get(n).copied().unwrap_or(0);
+        if peer_acked >= head || !node.in_flight.write().unwrap().insert(n.clone()) {
+            return;
+        }
+
+        let payload: SendPayload<C::State> = match log.since(peer_acked) {
+            Some(deltas) => SendPayload::ReplicateDelta(ReplicateDeltaPayload::new(deltas)),
+            None => SendPayload::Replicate(ReplicatePayload::new(node.crdt.data())),
+        };
+
+        let acked = Arc::clone(&node.acked);
+        let in_flight = Arc::clone(&node.in_flight);
+        let dest = n.clone();
+        node.ctx.rpc(
+            n,
+            payload,
+            RpcOptions::new(REPLICATE_TIMEOUT),
+            move |result| {
+                if result.is_ok() {
+                    let mut acked_guard = acked.write().unwrap();
+                    let peer_acked = acked_guard.entry(dest.clone()).or_insert(0);
+                    *peer_acked = (*peer_acked).max(head);
+                }
+                in_flight.write().unwrap().remove(&dest);
+            },
+        );
+    });
 }
 
-impl<C: CrdtTrait> fmt::Debug for Task<C> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_tuple("Task")
-            .field(&format_args!("Callback"))
-            .field(&format_args!("millisec: {:?}", self.millisec))
-            .finish()
+// Causal CRDTs: stamps the local delta, then sends every neighbor the
+// operations it did not ack yet, one batch at a time
+pub fn replicate_ops<C: CrdtTrait>(node: &Node<C>) {
+    let mut causal = node.causal.write().unwrap();
+    if let Some(delta) = node.crdt.take_delta() {
+        causal.stamp(&node.ctx.node_id, delta);
     }
+
+    let neighbors = node.neighbors.read().unwrap().clone();
+    neighbors.iter().for_each(|n| {
+        let ops = causal.missing(n, MAX_OPS);
+        if ops.is_empty() || !node.in_flight.write().unwrap().insert(n.clone()) {
+            return;
+        }
+
+        let payload: SendPayload<C::State> =
+            SendPayload::ReplicateOps(ReplicateOpsPayload::new(ops));
+        let causal_clone = Arc::clone(&node.causal);
+        let in_flight = Arc::clone(&node.in_flight);
+        let dest = n.clone();
+        node.ctx.rpc(
+            n,
+            payload,
+            RpcOptions::new(REPLICATE_TIMEOUT),
+            move |result| {
+                let reply = result.and_then(Message::into_typed::<SendPayload<C::State>>);
+                if let Ok(SendPayload::ReplicateOpsOk(ok)) = reply.map(|msg| msg.body.payload) {
+                    causal_clone.write().unwrap().ack(&dest, &ok.delivered);
+                }
+                in_flight.write().unwrap().remove(&dest);
+            },
+        );
+    });
+}
+
+// Forget the deltas every neighbor acked, and the operations every
+// neighbor delivered
+pub fn compact_deltas<C: CrdtTrait>(node: &Node<C>) {
+    if C::CAUSAL {
+        let neighbors = node.neighbors.read().unwrap();
+        node.causal.write().unwrap().prune(&neighbors);
+    }
+
+    let mut log = node.deltas.write().unwrap();
+    let acked = node.acked.read().unwrap();
+    let acked_by_all = node
+        .neighbors
+        .read()
+        .unwrap()
+        .iter()
+        .map(|n| acked.get(n).copied().unwrap_or(0))
+        .min()
+        .unwrap_or(log.head());
+    log.truncate(acked_by_all);
 }
 
-pub fn replicate_set<C: CrdtTrait>(node: &Node<C>) {
-    // ... replicate to all neighbors ...
-    // TODO : handle LockResult<RwLockReadGuard<'_, T>> error
-    if let Ok(neighbors_guard) = node.neighbors.read() {
-        neighbors_guard.iter().for_each(|n| {
-            let payload = SendPayload::Replicate(ReplicatePayload::new(node.crdt.data()));
-            let body = node.build_body(payload);
-            let msg = Message::new(n.to_owned(), body, node.node_id.clone());
-            msg.send()
-        });
+pub fn log_metrics<C: CrdtTrait>(node: &Node<C>) {
+    if C::CAUSAL {
+        let causal = node.causal.read().unwrap();
+        node.ctx.log(&format!(
+            "Metrics: {} ops kept, {} pending, delivered {:?}, in flight {:?}, {:?}",
+            causal.len(),
+            causal.pending(),
+            causal.delivered(),
+            node.in_flight.read().unwrap(),
+            node.ctx.rpc_stats(),
+        ));
+        return;
     }
+
+    let log = node.deltas.read().unwrap();
+    node.ctx.log(&format!(
+        "Metrics: {} deltas logged, head {}, acked {:?}, in flight {:?}, {:?}",
+        log.len(),
+        log.head(),
+        node.acked.read().unwrap(),
+        node.in_flight.read().unwrap(),
+        node.ctx.rpc_stats(),
+    ));
 }
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Number of operations seen from each node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VClock(pub HashMap<String, u64>);
+
+impl VClock {
+    pub fn get(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+
+    pub fn increment(&mut self, node: &str) -> u64 {
+        let counter = self.0.entry(node.to_owned()).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    pub fn merge(&mut self, other: &VClock) {
+        other.0.iter().for_each(|(node, &c)| {
+            let counter = self.0.entry(node.clone()).or_insert(0);
+            *counter = (*counter).max(c);
+        });
+    }
+
+    // Happened before, or equal
+    pub fn leq(&self, other: &VClock) -> bool {
+        self.0.iter().all(|(node, &c)| c <= other.get(node))
+    }
+
+    // Whether the operation of `origin` stamped with this clock comes right
+    // after `delivered` from `origin`, and everything it depends on from
+    // other nodes is already delivered
+    pub fn deliverable(&self, origin: &str, delivered: &VClock) -> bool {
+        self.get(origin) == delivered.get(origin) + 1
+            && self
+                .0
+                .iter()
+                .all(|(node, &c)| node == origin || c <= delivered.get(node))
+    }
+}
@@ -1,5 +1,4 @@
 pub mod msg;
 pub mod node;
-pub mod promise;
 pub mod thunk;
 pub mod txn;
@@ -1,106 +1,12 @@
-use serde::{self, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
+use serde::{self, Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::datomic::thunk::{Thunk, ThunkMap, ThunkValues, ThunkWriteEnum};
 use crate::datomic::txn::TxnOp;
+use crate::protocol::ErrorPayload;
 
-use super::thunk::ThunkTrait;
-
-#[derive(Debug, Clone)]
-struct LinKvBody {
-    key: usize,
-}
-
-impl Serialize for LinKvBody {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut s = serializer.serialize_map(Some(2))?;
-        s.serialize_entry("type", "init")?;
-        s.serialize_entry("key", &self.key)?;
-        s.end()
-    }
-}
-
-#[derive(Debug, Clone)]
-pub enum MessageDest {
-    LinKv,
-    VarDest(String),
-}
-
-const SVC: &str = "lin-kv";
-//const SVC: &str = "lww-kv";
-
-impl Serialize for MessageDest {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let dest = match &self {
-            MessageDest::LinKv => SVC,
-            MessageDest::VarDest(var_dest) => var_dest,
-        };
-
-        serializer.serialize_str(dest)
-    }
-}
-
-impl<'de> Deserialize<'de> for MessageDest {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let dest = String::deserialize(deserializer)?;
-        match dest.as_str() {
-            SVC => Ok(MessageDest::LinKv),
-            _ => Ok(MessageDest::VarDest(dest)),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "snake_case")]
-pub struct Message<P: PayloadTrait> {
-    pub src: String,
-    pub dest: MessageDest,
-    pub body: Body<P>,
-}
-
-impl<P> Message<P>
-where
-    P: PayloadTrait + Serialize,
-{
-    pub fn new(dest: MessageDest, body: Body<P>, src: String) -> Self {
-        Message { src, dest, body }
-    }
-
-    pub fn send(&self) {
-        let mut stderr = io::stderr().lock();
-        serde_json::to_writer(&mut stderr, &self).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-
-        let mut stdout = io::stdout().lock();
-        serde_json::to_writer(&mut stdout, &self).unwrap();
-        stdout.write_all(b"\n").unwrap();
-        stdout.flush().unwrap();
-    }
-
-    pub fn print(&self) {
-        let mut stderr = io::stderr().lock();
-        serde_json::to_writer(&mut stderr, &self).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-    }
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitPayload {
-    pub node_id: String,
-    pub node_ids: HashSet<String>,
-}
+pub const SVC: &str = "lin-kv";
+//pub const SVC: &str = "lww-kv";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TxnPayload {
@@ -134,18 +40,6 @@ pub struct LinKvReadMapOk {
     pub value: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct LinKvError {
-    pub code: usize,
-    pub text: String,
-}
-
-impl LinKvError {
-    pub fn new(code: usize, text: String) -> Self {
-        LinKvError { code, text }
-    }
-}
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum LinKvReplyValue {
@@ -154,7 +48,6 @@ pub enum LinKvReplyValue {
     ReadMapOk(LinKvReadMapOk),
     WriteOk(),
     CasOk(),
-    Error(LinKvError),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -184,8 +77,8 @@ pub struct LinKvReadRootPayload {
     pub key: String,
 }
 
-impl LinKvReadRootPayload {
-    pub fn new() -> Self {
+impl Default for LinKvReadRootPayload {
+    fn default() -> Self {
         LinKvReadRootPayload {
             key: "root".to_string(),
         }
@@ -253,29 +146,35 @@ impl LinKvCasRootPayload {
     }
 }
 
-pub trait PayloadTrait {}
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 //#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum ReqPayload {
-    #[serde(rename = "init")]
-    Init(InitPayload),
     #[serde(rename = "txn")]
     Txn(TxnPayload),
-    //#[serde(rename = "read_ok")]
-    //RootOk(LinKvReplyValue),
+}
+
+// Replies from lin-kv, error replies are turned into Err by the RPC client
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum LinKvReply {
     #[serde(rename = "read_ok")]
     ReadOk(LinKvReplyValue),
     #[serde(rename = "write_ok")]
     WriteOk,
     #[serde(rename = "cas_ok")]
     CasOk,
-    #[serde(rename = "error")]
-    LinKvError(LinKvError),
 }
 
-impl PayloadTrait for ReqPayload {}
+impl From<LinKvReply> for LinKvReplyValue {
+    fn from(reply: LinKvReply) -> Self {
+        match reply {
+            LinKvReply::ReadOk(value) => value,
+            LinKvReply::WriteOk => LinKvReplyValue::WriteOk(),
+            LinKvReply::CasOk => LinKvReplyValue::CasOk(),
+        }
+    }
+}
 
 pub trait SendTrait {}
 
@@ -283,16 +182,13 @@ pub trait SendTrait {}
 //#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum SendPayload {
-    #[serde(rename = "init_ok")]
-    InitOk,
     #[serde(rename = "txn_ok")]
     TxnOk(TxnOkPayload),
     #[serde(rename = "error")]
-    Error(LinKvError),
+    Error(ErrorPayload),
 }
 
 impl SendTrait for SendPayload {}
-impl PayloadTrait for SendPayload {}
 
 #[derive(Debug, Clone, Serialize)]
 //#[serde(untagged)]
@@ -310,27 +206,4 @@ pub enum LinKvPayload<'a> {
     Cas(LinKvCasPayload),
 }
 
-impl<'a> SendTrait for LinKvPayload<'a> {}
-impl<'a> PayloadTrait for LinKvPayload<'a> {}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Body<P: PayloadTrait> {
-    #[serde(flatten)]
-    pub payload: P,
-    pub msg_id: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub in_reply_to: Option<usize>,
-}
-
-impl<P> Body<P>
-where
-    P: PayloadTrait,
-{
-    pub fn new(payload: P, msg_id: Option<usize>, in_reply_to: Option<usize>) -> Self {
-        Body {
-            payload,
-            msg_id,
-            in_reply_to,
-        }
-    }
-}
+impl SendTrait for LinKvPayload<'_> {}
@@ -1,16 +1,24 @@
-use super::msg::{MessageDest, PayloadTrait, ReqPayload, SendPayload};
+use super::msg::{ReqPayload, SendPayload};
 use serde::Serialize;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::io::{self, Write};
-use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crate::datomic::msg::{
-    Body, LinKvCasRootPayload, LinKvError, LinKvPayload, LinKvReadPayload, LinKvReadRootPayload,
-    LinKvReplyValue, LinKvWritePayload, Message, TxnOkPayload,
+    LinKvCasRootPayload, LinKvPayload, LinKvReadRootPayload, LinKvReply, LinKvReplyValue,
+    LinKvWritePayload, TxnOkPayload, SVC,
 };
-use crate::datomic::promise::Promise;
-use crate::datomic::thunk::{Thunk, ThunkMap, ThunkValues, ThunkWriteEnum};
+use crate::datomic::thunk::{Thunk, ThunkMap, ThunkWriteEnum};
 use crate::datomic::txn::{TxnOp, TxnReadOp};
+use crate::protocol::{Body, ErrorCode, ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::dispatch::{Dispatch, DEFAULT_CAPACITY};
+use crate::runtime::handler::Handler;
+use crate::runtime::rpc::{RetryPolicy, RpcOptions};
+
+const LIN_KV_TIMEOUT: Duration = Duration::from_millis(25);
+const LIN_KV_MAX_ATTEMPTS: usize = 5;
 
 pub fn log<M>(msg: &M)
 where
@@ -24,34 +32,13 @@ where
 
 #[derive(Debug)]
 pub struct Node {
-    pub node_id: String,
-    next_msg_id: RwLock<usize>,
+    pub ctx: Arc<Context>,
     next_thunk_id: RwLock<usize>,
-    pub promise_map: RwLock<HashMap<usize, Arc<Promise>>>,
 }
 
 impl Node {
-    pub fn new(node_id: String) -> Self {
-        Node {
-            node_id,
-            next_msg_id: RwLock::new(1),
-            next_thunk_id: RwLock::new(0),
-            promise_map: RwLock::new(HashMap::new()),
-        }
-    }
-
-    pub fn build_body<P>(&self, payload: P, in_reply_to: Option<usize>) -> Body<P>
-    where
-        P: PayloadTrait,
-    {
-        let body = Body::new(
-            payload,
-            Some(self.next_msg_id.read().unwrap().clone()),
-            in_reply_to,
-        );
-        let mut nxt_msg_id_guard = self.next_msg_id.write().unwrap();
-        *nxt_msg_id_guard += 1;
-        body
+    pub fn build_body<P>(&self, payload: P, in_reply_to: Option<usize>) -> Body<P> {
+        self.ctx.build_body(payload, in_reply_to)
     }
 
     fn new_id(&self) -> usize {
@@ -61,30 +48,36 @@ impl Node {
         id
     }
 
-    pub fn new_promise(&self, promise_msg_id: usize) -> Arc<Promise> {
-        let mut promise_map_guard = self.promise_map.write().unwrap();
-        let promise = Arc::new(Promise::new());
-        let stored_promise = promise.clone();
-        promise_map_guard.insert(promise_msg_id, stored_promise);
-        promise
+    // Blocking lin-kv round trip. Reads and writes of thunks are idempotent
+    // and retried, a CAS on root is not: a lost cas_ok must not apply twice.
+    pub fn lin_kv(&self, payload: LinKvPayload) -> Result<LinKvReplyValue, ErrorPayload> {
+        let opts = match payload {
+            LinKvPayload::CasRoot(_) | LinKvPayload::Cas(_) => RpcOptions::new(LIN_KV_TIMEOUT),
+            _ => RpcOptions::new(LIN_KV_TIMEOUT).with_retry(RetryPolicy::exponential(
+                Some(LIN_KV_MAX_ATTEMPTS),
+                LIN_KV_TIMEOUT * 8,
+            )),
+        };
+        let reply = self.ctx.sync_rpc::<_, LinKvReply>(SVC, payload, opts)?;
+        Ok(reply.body.payload.into())
     }
 
     pub fn map_transact(
         &self,
         map0: &mut Thunk<ThunkMap>,
-        txn0: &Vec<TxnOp>,
-    ) -> Result<(Thunk<ThunkMap>, Vec<TxnOp>), LinKvError> {
-        let mut map_value = map0.get_value(&self)?;
+        txn0: &[TxnOp],
+    ) -> Result<(Thunk<ThunkMap>, Vec<TxnOp>), ErrorPayload> {
+        let mut map_value = map0.get_value(self)?;
         eprintln!("map_value: {:?}", map_value);
 
-        let txn1_res: Result<Vec<TxnOp>, LinKvError> =
-            txn0.into_iter().try_fold(vec![], |mut accu, mop| {
+        let txn1_res: Result<Vec<TxnOp>, ErrorPayload> =
+            txn0.iter().try_fold(vec![], |mut accu, mop| {
                 let key = mop.get_key();
                 match mop {
                     TxnOp::Read(_) => {
                         let new_txn = {
                             if let Some(thunk) = map_value.get_mut(&key) {
-                                let thunk_value = thunk.get_value(&self)?;
+                                let thunk_value = thunk.get_value(self)?;
                                 TxnOp::Read(TxnReadOp::new(key, thunk_value))
                             } else {
                                 TxnOp::Read(TxnReadOp::new(key, vec![]))
@@ -96,7 +89,7 @@ impl Node {
                     TxnOp::Append(append_op) => {
                         let mut new_thunk_value = {
                             if let Some(thunk) = map_value.get_mut(&key) {
-                                thunk.get_value(&self)?
+                                thunk.get_value(self)?
                             } else {
                                 vec![]
                             }
@@ -104,7 +97,7 @@ impl Node {
 
                         new_thunk_value.push(append_op.value);
                         let new_thunk =
-                            Thunk::new(self.node_id.to_owned(), self.new_id(), new_thunk_value);
+                            Thunk::new(self.ctx.node_id.to_owned(), self.new_id(), new_thunk_value);
 
                         map_value.insert(key, new_thunk);
 
@@ -120,7 +113,7 @@ impl Node {
                 // -> avoid useless read error when getting values
                 map0.clone()
             } else {
-                Thunk::new(self.node_id.to_owned(), self.new_id(), map_value)
+                Thunk::new(self.ctx.node_id.to_owned(), self.new_id(), map_value)
             }
         };
 
@@ -131,53 +124,45 @@ impl Node {
         Ok((map1, txn1))
     }
 
-    fn init_map(&self, map_id: String, thunk_write_enum: ThunkWriteEnum) -> Result<(), LinKvError> {
+    fn init_map(
+        &self,
+        map_id: String,
+        thunk_write_enum: ThunkWriteEnum,
+    ) -> Result<(), ErrorPayload> {
         // init root value
         let write_payload = LinKvWritePayload::new(map_id.to_owned(), &thunk_write_enum);
-        let init_body = self.build_body(LinKvPayload::Write(write_payload), None);
-        let promise_msg_id = init_body.msg_id.unwrap();
-        self.new_promise(promise_msg_id)
-            .sync_rpc(self.node_id.clone(), init_body)?;
+        self.lin_kv(LinKvPayload::Write(write_payload))?;
         Ok(())
     }
 
-    fn cas_root(&self, map0: Thunk<ThunkMap>, map1: Thunk<ThunkMap>) -> Result<(), LinKvError> {
+    fn cas_root(&self, map0: Thunk<ThunkMap>, map1: Thunk<ThunkMap>) -> Result<(), ErrorPayload> {
         let cas_payload = LinKvCasRootPayload::new(map0, map1);
-        let cas_body = self.build_body(LinKvPayload::CasRoot(cas_payload), None);
-        let cas_msg_id = cas_body.msg_id.unwrap();
-
-        let cas_promise = self.new_promise(cas_msg_id);
-
-        let cas_promise_res = cas_promise.sync_rpc(self.node_id.clone(), cas_body)?;
-        if let Some(LinKvReplyValue::CasOk()) = cas_promise_res {
+        if let LinKvReplyValue::CasOk() = self.lin_kv(LinKvPayload::CasRoot(cas_payload))? {
             eprintln!("CAS succeded!");
-            return Ok(());
+            Ok(())
         } else {
             eprintln!("CAS root failed!");
-            let cas_error = LinKvError::new(30, "CAS failed!".to_owned());
-            return Err(cas_error);
+            let cas_error = ErrorPayload::new(ErrorCode::TxnConflict, "CAS failed!".to_owned());
+            Err(cas_error)
         }
     }
 
-    fn transact(&self, txn0: &Vec<TxnOp>) -> Result<Vec<TxnOp>, LinKvError> {
+    fn transact(&self, txn0: &[TxnOp]) -> Result<Vec<TxnOp>, ErrorPayload> {
         // read value from key with lin-kv
-        let read_body = self.build_body(LinKvPayload::Root(LinKvReadRootPayload::new()), None);
-        let promise_msg_id = read_body.msg_id.unwrap();
-        let root_res = self
-            .new_promise(promise_msg_id)
-            .sync_rpc(self.node_id.clone(), read_body);
+        let root_res = self.lin_kv(LinKvPayload::Root(LinKvReadRootPayload::default()));
 
         //let mut map0: Thunk<ThunkMap> = HashMap::new();
         let mut map0 = match root_res {
-            Ok(Some(LinKvReplyValue::RootOk(r_p))) => Ok(Thunk::from_id(r_p.value)),
-            Err(LinKvError { code: 20, text: _ }) => {
-                log(&format!(
-                    "LinKv returned empty root for: {}",
-                    promise_msg_id
-                ));
+            Ok(LinKvReplyValue::RootOk(r_p)) => Ok(Thunk::from_id(r_p.value)),
+            Err(ErrorPayload {
+                code: ErrorCode::KeyDoesNotExist,
+                ..
+            }) => {
+                log(&"LinKv returned empty root".to_owned());
 
                 // Dummy request to create {} at root
-                let new_map = Thunk::new(self.node_id.to_owned(), self.new_id(), ThunkMap::new());
+                let new_map =
+                    Thunk::new(self.ctx.node_id.to_owned(), self.new_id(), ThunkMap::new());
 
                 // init root value
                 self.init_map(
@@ -189,11 +174,9 @@ impl Node {
 
                 Ok(new_map)
             }
-            _ => {
-                let abort_error = LinKvError::new(
-                    14,
-                    format!("Could not read root from: {:?}", promise_msg_id),
-                );
+            res => {
+                let abort_error =
+                    ErrorPayload::new(ErrorCode::Abort, format!("Could not read root: {:?}", res));
                 Err(abort_error)
             }
         }?;
@@ -201,24 +184,39 @@ impl Node {
         let (mut map1, txn1) = self.map_transact(&mut map0, txn0)?;
 
         // Save all thunks values
-        map1.save(&self)?;
+        map1.save(self)?;
 
         self.cas_root(map0, map1)?;
 
         Ok(txn1)
     }
+}
 
-    pub fn forward_to_promise(&self, reply_to: usize, reply: LinKvReplyValue) {
-        let mut promise_map_guard = self.promise_map.write().unwrap();
-        if let Some(promise) = promise_map_guard.remove(&reply_to) {
-            promise.deliver(reply);
-        } else {
-            log(&format!("Promise not found for : {}", reply_to));
+impl Handler for Node {
+    type Payload = ReqPayload;
+
+    fn init(ctx: Arc<Context>) -> Self {
+        Node {
+            ctx,
+            next_thunk_id: RwLock::new(0),
+        }
+    }
+
+    fn handle(&self, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
+        handle_msg(request, self);
+        Ok(())
+    }
+
+    // Each txn blocks its worker on lin-kv round trips
+    fn dispatch() -> Dispatch {
+        Dispatch::Pool {
+            workers: 32,
+            capacity: DEFAULT_CAPACITY,
         }
     }
 }
 
-pub fn handle_msg(request: Message<ReqPayload>, node: Arc<Node>) -> Result<(), ()> {
+fn handle_msg(request: Message<ReqPayload>, node: &Node) {
     eprintln!("Body : {:?}", request.body);
     let Body {
         payload: req_payload,
@@ -228,7 +226,6 @@ pub fn handle_msg(request: Message<ReqPayload>, node: Arc<Node>) -> Result<(), (
 
     // send response
     let (reply_payload_opt, id_reply_opt) = match (req_payload, msg_id_opt, reply_to_opt) {
-        (ReqPayload::Init(_), Some(msg_id_opt), _) => (Some(SendPayload::InitOk), Some(msg_id_opt)),
         (ReqPayload::Txn(p), Some(msg_id_opt), _) => {
             let payload = match node.transact(&p.txn) {
                 Ok(txn2) => {
@@ -239,26 +236,6 @@ pub fn handle_msg(request: Message<ReqPayload>, node: Arc<Node>) -> Result<(), (
             };
             (payload, Some(msg_id_opt))
         }
-        (ReqPayload::ReadOk(value), _, Some(reply_to)) => {
-            eprintln!("Promise read ok : {:?} to {:}", value, reply_to);
-            node.forward_to_promise(reply_to, value);
-            (None, None)
-        }
-        (ReqPayload::WriteOk, _, Some(reply_to)) => {
-            eprintln!("Promise write ok to: {:?}", reply_to);
-            node.forward_to_promise(reply_to, LinKvReplyValue::WriteOk());
-            (None, None)
-        }
-        (ReqPayload::CasOk, _, Some(reply_to)) => {
-            eprintln!("Promise cas ok to: {:?}", reply_to);
-            node.forward_to_promise(reply_to, LinKvReplyValue::CasOk());
-            (None, None)
-        }
-        (ReqPayload::LinKvError(err), _, Some(reply_to)) => {
-            eprintln!("Linkv error: {:?} replying to: {:}", err, &reply_to);
-            node.forward_to_promise(reply_to, LinKvReplyValue::Error(err));
-            (None, None)
-        }
         _ => (None, None),
     };
 
@@ -266,13 +243,7 @@ pub fn handle_msg(request: Message<ReqPayload>, node: Arc<Node>) -> Result<(), (
     if let Some(payload) = reply_payload_opt {
         let body = node.build_body(payload, id_reply_opt);
         log(&format!("Send {:?}", body));
-        Message::new(
-            MessageDest::VarDest(request.src.to_owned()),
-            body,
-            node.node_id.clone(),
-        )
-        .send();
+        let msg = node.ctx.build_msg(&request.src, body);
+        node.ctx.send_msg(&msg);
     }
-
-    Ok(())
 }
@@ -1,11 +1,9 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
+use std::collections::HashMap;
 
-use crate::datomic::msg::{
-    LinKvError, LinKvPayload, LinKvReadPayload, LinKvReplyValue, LinKvWritePayload,
-};
+use crate::datomic::msg::{LinKvPayload, LinKvReadPayload, LinKvReplyValue, LinKvWritePayload};
 use crate::datomic::node::{log, Node};
+use crate::protocol::ErrorPayload;
 
 #[derive(Debug, Clone)]
 pub enum ThunkWriteEnum<'a> {
@@ -37,8 +35,8 @@ pub trait ThunkTrait {
     fn is_empty(&self) -> bool;
     fn new() -> Self;
     fn unwrap_reply(reply: LinKvReplyValue) -> Option<Box<Self>>;
-    fn to_write_value(&self) -> ThunkWriteEnum;
-    fn save(&mut self, id: String, node: &Node) -> Result<Option<LinKvReplyValue>, LinKvError>;
+    fn to_write_value(&self) -> ThunkWriteEnum<'_>;
+    fn save(&mut self, id: String, node: &Node) -> Result<LinKvReplyValue, ErrorPayload>;
 }
 
 #[derive(Debug, Clone)]
@@ -66,18 +64,16 @@ impl ThunkTrait for ThunkValues {
         }
     }
 
-    fn to_write_value(&self) -> ThunkWriteEnum {
+    fn to_write_value(&self) -> ThunkWriteEnum<'_> {
         ThunkWriteEnum::Thunk(self)
     }
 
-    fn save(&mut self, id: String, node: &Node) -> Result<Option<LinKvReplyValue>, LinKvError> {
+    fn save(&mut self, id: String, node: &Node) -> Result<LinKvReplyValue, ErrorPayload> {
         let write_value = self.to_write_value();
-        let write_body = node.build_body(
-            LinKvPayload::Write(LinKvWritePayload::new(id, &write_value)),
-            None,
-        );
-        let promise = node.new_promise(write_body.msg_id.unwrap());
-        promise.sync_rpc(node.node_id.clone(), write_body)
+        node.lin_kv(LinKvPayload::Write(LinKvWritePayload::new(
+            id,
+            &write_value,
+        )))
     }
 }
 
@@ -107,24 +103,22 @@ impl ThunkTrait for ThunkMap {
         }
     }
 
-    fn to_write_value(&self) -> ThunkWriteEnum {
+    fn to_write_value(&self) -> ThunkWriteEnum<'_> {
         ThunkWriteEnum::Map(self)
     }
 
-    fn save(&mut self, id: String, node: &Node) -> Result<Option<LinKvReplyValue>, LinKvError> {
+    fn save(&mut self, id: String, node: &Node) -> Result<LinKvReplyValue, ErrorPayload> {
         self.iter_mut().try_for_each(|(_, v)| {
-            v.save(&node)?;
+            v.save(node)?;
             Ok(())
         })?;
 
         let write_value = self.to_write_value();
 
-        let write_body = node.build_body(
-            LinKvPayload::Write(LinKvWritePayload::new(id, &write_value)),
-            None,
-        );
-        let promise = node.new_promise(write_body.msg_id.unwrap());
-        promise.sync_rpc(node.node_id.clone(), write_body)
+        node.lin_kv(LinKvPayload::Write(LinKvWritePayload::new(
+            id,
+            &write_value,
+        )))
     }
 }
 
@@ -146,32 +140,25 @@ impl<V: Clone + ThunkTrait> Thunk<V> {
         }
     }
 
-    pub fn get_value(&mut self, node: &Node) -> Result<V, LinKvError> {
+    pub fn get_value(&mut self, node: &Node) -> Result<V, ErrorPayload> {
         if self.value.is_empty() {
-            let read_body = node.build_body(
-                LinKvPayload::Read(LinKvReadPayload::new(self.id.to_owned())),
-                None,
-            );
-            let promise_msg_id = read_body.msg_id.unwrap();
-            let promise = node.new_promise(promise_msg_id);
-
-            let promise_res = promise.sync_rpc(node.node_id.clone(), read_body)?;
-            if let Some(reply) = V::unwrap_reply(promise_res.unwrap()) {
+            let read_payload = LinKvPayload::Read(LinKvReadPayload::new(self.id.to_owned()));
+            if let Some(reply) = V::unwrap_reply(node.lin_kv(read_payload)?) {
                 self.value = *reply;
             } else {
                 log(&format!("Unable to read value for thunk {}", self.id));
             }
         }
 
-        return Ok(self.value.clone());
+        Ok(self.value.clone())
     }
 
-    pub fn save(&mut self, node: &Node) -> Result<(), LinKvError> {
+    pub fn save(&mut self, node: &Node) -> Result<(), ErrorPayload> {
         if !self.saved {
             self.get_value(node)?;
 
             let save_res = self.value.save(self.id.to_owned(), node)?;
-            if let Some(LinKvReplyValue::WriteOk()) = save_res {
+            if let LinKvReplyValue::WriteOk() = save_res {
                 self.saved = true;
             } else {
                 log(&format!("Unable to save thunk {}", self.id));
@@ -11,7 +11,7 @@ pub struct TxnReadOp {
 
 impl TxnReadOp {
     pub fn new(key: usize, value: Vec<usize>) -> Self {
-        TxnReadOp { key, value: value }
+        TxnReadOp { key, value }
     }
 }
 
@@ -89,7 +89,7 @@ impl<'de> Deserialize<'de> for TxnOp {
                         let value_opt: Option<Vec<usize>> = seq
                             .next_element()?
                             .ok_or_else(|| de::Error::invalid_length(2, &self))?;
-                        let value = value_opt.unwrap_or_else(|| vec![]);
+                        let value = value_opt.unwrap_or_default();
                         Ok(TxnOp::Read(TxnReadOp { key, value }))
                     }
                     "append" => {
@@ -1,46 +1,66 @@
-use crate::echo::msg::{Body, EchoOkPayload, InitOkPayload, Message, Payload};
-use crate::output::to_stderr;
+use std::sync::Arc;
+
+use crate::echo::msg::{EchoPayload, ReqPayload, SendPayload};
+use crate::protocol::{ErrorPayload, Message};
+#[cfg(feature = "async")]
+use crate::runtime::async_runtime::AsyncHandler;
+use crate::runtime::context::Context;
+use crate::runtime::dispatch::Dispatch;
+use crate::runtime::handler::Handler;
 
 #[derive(Debug)]
 pub struct Node {
-    node_id: String,
-    next_msg_id: usize,
+    ctx: Arc<Context>,
 }
 
-impl Node {
-    pub fn new(node_id: String) -> Self {
-        Node {
-            node_id,
-            next_msg_id: 0,
-        }
-    }
+impl Handler for Node {
+    type Payload = ReqPayload;
 
-    pub fn handle_msg(&mut self, request: &Message) -> Result<Message, ()> {
-        let req_msg_id = request.body.msg_id;
+    fn init(ctx: Arc<Context>) -> Self {
+        ctx.log(&format!("Initiated node {:}", ctx.node_id));
+        Node { ctx }
+    }
 
+    fn handle(&self, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
         let payload = match &request.body.payload {
-            Payload::Init(init_p) => {
-                to_stderr(&format!("Initiated node {:}", init_p.get()).to_string());
-                Ok(Payload::InitOk(InitOkPayload::new(req_msg_id)))
+            ReqPayload::Echo(echo_p) => {
+                self.ctx.log(&format!("Echoing {:}", echo_p.get()));
+                SendPayload::EchoOk(EchoPayload::new(echo_p.get()))
             }
-            Payload::Echo(echo_p) => {
-                to_stderr(&format!("Echoing {:}", echo_p.get()).to_string());
-                Ok(Payload::EchoOk(EchoOkPayload::new(
-                    echo_p.get(),
-                    req_msg_id,
-                )))
-            }
-            _ => Err(()),
-        }?;
+        };
+
+        self.ctx.reply(&request, payload);
 
-        let body_send = Body::new(payload, self.next_msg_id);
+        Ok(())
+    }
+
+    fn dispatch() -> Dispatch {
+        Dispatch::Sequential
+    }
+}
+
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct AsyncNode {
+    ctx: Arc<Context>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncHandler for AsyncNode {
+    type Payload = ReqPayload;
+
+    fn init(ctx: Arc<Context>) -> Self {
+        ctx.log(&format!("Initiated node {:}", ctx.node_id));
+        AsyncNode { ctx }
+    }
+
+    async fn handle(self: Arc<Self>, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
+        let payload = match &request.body.payload {
+            ReqPayload::Echo(echo_p) => SendPayload::EchoOk(EchoPayload::new(echo_p.get())),
+        };
 
-        self.next_msg_id += 1;
+        self.ctx.reply(&request, payload);
 
-        Ok(Message::new(
-            request.src.to_string(),
-            body_send,
-            self.node_id.clone(),
-        ))
+        Ok(())
     }
 }
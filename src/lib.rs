@@ -3,4 +3,7 @@ pub mod crdt;
 pub mod datomic;
 pub mod echo;
 pub mod output;
+pub mod protocol;
 pub mod raft;
+pub mod runtime;
+pub mod unique_ids;
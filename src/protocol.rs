@@ -0,0 +1,171 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+// Maelstrom envelope shared by every workload: only the payload, flattened
+// in the body next to `type`, differs from one workload to another.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Message<P> {
+    pub src: String,
+    pub dest: String,
+    pub body: Body<P>,
+}
+
+impl<P> Message<P> {
+    pub fn new(src: String, dest: String, body: Body<P>) -> Self {
+        Message { src, dest, body }
+    }
+}
+
+impl Message<Value> {
+    // Parse a raw payload into the workload's own payload type. A `type`
+    // the workload does not know is not supported, bad fields are malformed.
+    pub fn into_typed<P: DeserializeOwned>(self) -> Result<Message<P>, ErrorPayload> {
+        let Message { src, dest, body } = self;
+        match serde_json::from_value(body.payload) {
+            Ok(payload) => Ok(Message::new(
+                src,
+                dest,
+                Body::new(payload, body.msg_id, body.in_reply_to),
+            )),
+            // Serde's message for a tag matching no variant
+            Err(e) if e.to_string().starts_with("unknown variant") => Err(ErrorPayload::new(
+                ErrorCode::NotSupported,
+                format!("Unsupported message: {}", e),
+            )),
+            Err(e) => Err(ErrorPayload::new(
+                ErrorCode::MalformedRequest,
+                format!("Error parsing message: {}", e),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Body<P> {
+    #[serde(flatten)]
+    pub payload: P,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<usize>,
+}
+
+impl<P> Body<P> {
+    pub fn new(payload: P, msg_id: Option<usize>, in_reply_to: Option<usize>) -> Self {
+        Body {
+            payload,
+            msg_id,
+            in_reply_to,
+        }
+    }
+
+    // Same ids, without the payload
+    pub fn clone_header(&self) -> Body<()> {
+        Body::new((), self.msg_id, self.in_reply_to)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InitPayload {
+    pub node_id: String,
+    #[serde(default)]
+    pub node_ids: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum InitReqPayload {
+    #[serde(rename = "init")]
+    Init(InitPayload),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum InitOkPayload {
+    #[serde(rename = "init_ok")]
+    InitOk,
+}
+
+// Standard Maelstrom error codes, see maelstrom/doc/protocol.md
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "usize", into = "usize")]
+pub enum ErrorCode {
+    Timeout,
+    NodeNotFound,
+    NotSupported,
+    TemporarilyUnavailable,
+    MalformedRequest,
+    Crash,
+    Abort,
+    KeyDoesNotExist,
+    KeyAlreadyExists,
+    PreconditionFailed,
+    TxnConflict,
+    Custom(usize),
+}
+
+impl From<usize> for ErrorCode {
+    fn from(code: usize) -> Self {
+        match code {
+            0 => ErrorCode::Timeout,
+            1 => ErrorCode::NodeNotFound,
+            10 => ErrorCode::NotSupported,
+            11 => ErrorCode::TemporarilyUnavailable,
+            12 => ErrorCode::MalformedRequest,
+            13 => ErrorCode::Crash,
+            14 => ErrorCode::Abort,
+            20 => ErrorCode::KeyDoesNotExist,
+            21 => ErrorCode::KeyAlreadyExists,
+            22 => ErrorCode::PreconditionFailed,
+            30 => ErrorCode::TxnConflict,
+            code => ErrorCode::Custom(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for usize {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Timeout => 0,
+            ErrorCode::NodeNotFound => 1,
+            ErrorCode::NotSupported => 10,
+            ErrorCode::TemporarilyUnavailable => 11,
+            ErrorCode::MalformedRequest => 12,
+            ErrorCode::Crash => 13,
+            ErrorCode::Abort => 14,
+            ErrorCode::KeyDoesNotExist => 20,
+            ErrorCode::KeyAlreadyExists => 21,
+            ErrorCode::PreconditionFailed => 22,
+            ErrorCode::TxnConflict => 30,
+            ErrorCode::Custom(code) => code,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ErrorPayload {
+    pub code: ErrorCode,
+    pub text: String,
+}
+
+impl ErrorPayload {
+    pub fn new(code: ErrorCode, text: String) -> Self {
+        ErrorPayload { code, text }
+    }
+}
+
+impl fmt::Display for ErrorPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error {}: {}", usize::from(self.code), self.text)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum ErrorReplyPayload {
+    #[serde(rename = "error")]
+    Error(ErrorPayload),
+}
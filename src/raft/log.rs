@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::raft::msg::OpPayload;
+use crate::raft::node::Map;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LogEntry {
+    pub term: u64,
+    pub op: OpPayload,
+}
+
+// State machine as of an index of the log, standing for every entry up to
+// it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub last_index: u64,
+    pub last_term: u64,
+    pub map: Map,
+}
+
+// Replicated operations, indexed from 1: index 0 is the empty log, of
+// term 0. Entries up to `base_index` are compacted into a snapshot.
+#[derive(Debug, Default)]
+pub struct Log {
+    base_index: u64,
+    base_term: u64,
+    entries: Vec<LogEntry>,
+}
+
+impl Log {
+    pub fn base_index(&self) -> u64 {
+        self.base_index
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.base_index + self.entries.len() as u64
+    }
+
+    pub fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.base_term, |entry| entry.term)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // None once compacted
+    pub fn get(&self, index: u64) -> Option<&LogEntry> {
+        index
+            .checked_sub(self.base_index + 1)
+            .and_then(|i| self.entries.get(i as usize))
+    }
+
+    pub fn term_at(&self, index: u64) -> Option<u64> {
+        if index == self.base_index {
+            Some(self.base_term)
+        } else {
+            self.get(index).map(|entry| entry.term)
+        }
+    }
+
+    // Returns the index of the entry
+    pub fn append(&mut self, entry: LogEntry) -> u64 {
+        self.entries.push(entry);
+        self.last_index()
+    }
+
+    // Up to `max` entries starting at `index`, past the base
+    pub fn entries_from(&self, index: u64, max: usize) -> Vec<LogEntry> {
+        let start = index.saturating_sub(self.base_index + 1) as usize;
+        self.entries.iter().skip(start).take(max).cloned().collect()
+    }
+
+    // Entries sent by the leader right after `prev_index`: the ones already
+    // here are kept, so that a late, shorter append cannot drop entries, and
+    // everything from the first conflicting one on is replaced. Compacted
+    // entries are committed, so they match. Returns the entries written.
+    pub fn merge(&mut self, prev_index: u64, entries: Vec<LogEntry>) -> Vec<(u64, LogEntry)> {
+        let mut written = vec![];
+        for (i, entry) in (prev_index + 1..).zip(entries) {
+            if i <= self.base_index || self.term_at(i) == Some(entry.term) {
+                continue;
+            }
+            self.put(i, entry.clone());
+            written.push((i, entry));
+        }
+        written
+    }
+
+    // Writes the entry at `index`, dropping any following it
+    pub fn put(&mut self, index: u64, entry: LogEntry) {
+        if index <= self.base_index || index > self.last_index() + 1 {
+            return;
+        }
+        self.entries
+            .truncate((index - self.base_index - 1) as usize);
+        self.entries.push(entry);
+    }
+
+    // Drops the entries up to `index`, covered by a snapshot
+    pub fn compact(&mut self, index: u64) {
+        if index <= self.base_index || index > self.last_index() {
+            return;
+        }
+        self.base_term = self.term_at(index).unwrap();
+        self.entries.drain(..(index - self.base_index) as usize);
+        self.base_index = index;
+    }
+
+    // Restarts from a snapshot received from the leader, keeping the
+    // entries following it if they agree with it
+    pub fn reset(&mut self, index: u64, term: u64) {
+        if self.term_at(index) == Some(term) {
+            self.compact(index);
+        } else {
+            self.entries.clear();
+            self.base_index = index;
+            self.base_term = term;
+        }
+    }
+}
@@ -1,2 +1,6 @@
+pub mod log;
 pub mod msg;
 pub mod node;
+pub mod state;
+pub mod storage;
+pub mod tasks;
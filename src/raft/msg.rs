@@ -1,68 +1,14 @@
-use serde::{self, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
+use serde::{self, Deserialize, Serialize};
 
+use crate::protocol::ErrorPayload;
+use crate::raft::log::{LogEntry, Snapshot};
 use crate::raft::node::Map;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "snake_case")]
-pub struct Message<P: PayloadTrait> {
-    pub src: String,
-    pub dest: String,
-    pub body: Body<P>,
-}
-
-impl<P> Message<P>
-where
-    P: PayloadTrait + Serialize,
-{
-    pub fn new(body: Body<P>, dest: String, src: String) -> Self {
-        Message { src, dest, body }
-    }
-
-    pub fn send(&self) {
-        let mut stderr = io::stderr().lock();
-        serde_json::to_writer(&mut stderr, &self).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-
-        let mut stdout = io::stdout().lock();
-        serde_json::to_writer(&mut stdout, &self).unwrap();
-        stdout.write_all(b"\n").unwrap();
-        stdout.flush().unwrap();
-    }
-
-    pub fn print(&self) {
-        let mut stderr = io::stderr().lock();
-        serde_json::to_writer(&mut stderr, &self).unwrap();
-        stderr.write_all(b"\n").unwrap();
-        stderr.flush().unwrap();
-    }
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InitPayload {
-    pub node_id: String,
-    pub node_ids: HashSet<String>,
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct ErrorPayload {
-    pub code: usize,
-    pub text: String,
-}
-
-impl ErrorPayload {
-    pub fn new(code: usize, text: String) -> Self {
-        ErrorPayload { code, text }
-    }
-}
-
 pub trait OpPayloadTrait {
     fn apply(&self, map: &mut Map) -> Result<SendPayload, ErrorPayload>;
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ReadPayload {
     pub key: usize,
 }
@@ -79,18 +25,7 @@ impl OpPayloadTrait for ReadPayload {
     }
 }
 
-/*#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct LinKvReadOkPayload {
-    pub value: String,
-}
-
-impl LinKvReadOkPayload {
-    pub fn new(value: String) -> Self {
-        LinKvReadOkPayload { value }
-    }
-}*/
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct WritePayload {
     pub key: usize,
     pub value: usize,
@@ -107,7 +42,7 @@ impl OpPayloadTrait for WritePayload {
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct CasPayload {
     pub key: usize,
     pub from: usize,
@@ -137,80 +72,117 @@ impl ReadOkPayload {
     }
 }
 
-pub trait PayloadTrait {}
+// Client operations, as replicated in the log
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum OpPayload {
+    #[serde(rename = "read")]
+    Read(ReadPayload),
+    #[serde(rename = "write")]
+    Write(WritePayload),
+    #[serde(rename = "cas")]
+    Cas(CasPayload),
+}
+
+impl OpPayloadTrait for OpPayload {
+    fn apply(&self, map: &mut Map) -> Result<SendPayload, ErrorPayload> {
+        match self {
+            OpPayload::Read(op_p) => op_p.apply(map),
+            OpPayload::Write(op_p) => op_p.apply(map),
+            OpPayload::Cas(op_p) => op_p.apply(map),
+        }
+    }
+}
+
+// A pre-vote asks whether the vote would be granted at `term`, without
+// anyone's term or vote changing
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestVotePayload {
+    pub term: u64,
+    pub candidate_id: String,
+    pub last_log_index: u64,
+    pub last_log_term: u64,
+    #[serde(default)]
+    pub pre_vote: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequestVoteOkPayload {
+    pub term: u64,
+    pub vote_granted: bool,
+}
+
+// Entries following `prev_log_index`, none for a heartbeat
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppendEntriesPayload {
+    pub term: u64,
+    pub leader_id: String,
+    pub prev_log_index: u64,
+    pub prev_log_term: u64,
+    pub entries: Vec<LogEntry>,
+    pub leader_commit: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppendEntriesOkPayload {
+    pub term: u64,
+    pub success: bool,
+    // Last index known to match the leader's log on success, where to
+    // retry from otherwise
+    pub match_index: u64,
+}
+
+// Sent instead of entries the leader compacted already
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstallSnapshotPayload {
+    pub term: u64,
+    pub leader_id: String,
+    pub snapshot: Snapshot,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstallSnapshotOkPayload {
+    pub term: u64,
+}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-//#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum ReqPayload {
-    #[serde(rename = "init")]
-    Init(InitPayload),
     #[serde(rename = "read")]
     Read(ReadPayload),
     #[serde(rename = "write")]
     Write(WritePayload),
     #[serde(rename = "cas")]
     Cas(CasPayload),
+    #[serde(rename = "request_vote")]
+    RequestVote(RequestVotePayload),
+    #[serde(rename = "append_entries")]
+    AppendEntries(AppendEntriesPayload),
+    #[serde(rename = "install_snapshot")]
+    InstallSnapshot(InstallSnapshotPayload),
     #[serde(rename = "error")]
     Error(ErrorPayload),
 }
 
-impl PayloadTrait for ReqPayload {}
-
 pub trait SendTrait {}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-//#[serde(untagged)]
 #[serde(tag = "type")]
 pub enum SendPayload {
-    #[serde(rename = "init_ok")]
-    InitOk,
     #[serde(rename = "read_ok")]
     ReadOk(ReadOkPayload),
     #[serde(rename = "write_ok")]
     WriteOk,
     #[serde(rename = "cas_ok")]
     CasOk,
+    #[serde(rename = "request_vote_ok")]
+    RequestVoteOk(RequestVoteOkPayload),
+    #[serde(rename = "append_entries_ok")]
+    AppendEntriesOk(AppendEntriesOkPayload),
+    #[serde(rename = "install_snapshot_ok")]
+    InstallSnapshotOk(InstallSnapshotOkPayload),
     #[serde(rename = "error")]
     Error(ErrorPayload),
 }
 
 impl SendTrait for SendPayload {}
-impl PayloadTrait for SendPayload {}
-
-/*#[derive(Debug, Clone, Serialize)]
-//#[serde(untagged)]
-#[serde(tag = "type")]
-pub enum LinKvPayload {
-    #[serde(rename = "read")]
-    Read(LinKvReadPayload),
-    #[serde(rename = "write")]
-    Write(LinKvWritePayload),
-    #[serde(rename = "cas")]
-    Cas(LinKvCasPayload),
-}*/
-
-// impl SendTrait for LinKvPayload {}
-// impl PayloadTrait for LinKvPayload {}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Body<P: PayloadTrait> {
-    #[serde(flatten)]
-    pub payload: P,
-    pub msg_id: Option<usize>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub in_reply_to: Option<usize>,
-}
-
-impl<P> Body<P>
-where
-    P: PayloadTrait,
-{
-    pub fn new(payload: P, msg_id: Option<usize>, in_reply_to: Option<usize>) -> Self {
-        Body {
-            payload,
-            msg_id,
-            in_reply_to,
-        }
-    }
-}
@@ -1,14 +1,22 @@
-use serde::Serialize;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{self, Write};
-use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use crate::protocol::{Body, ErrorCode, ErrorPayload, Message};
 use crate::raft::msg::{
-    Body, ErrorPayload, Message, OpPayloadTrait, PayloadTrait, ReadOkPayload, ReadPayload,
-    ReqPayload, SendPayload, WritePayload,
+    InstallSnapshotOkPayload, InstallSnapshotPayload, OpPayload, OpPayloadTrait, ReadOkPayload,
+    ReqPayload, SendPayload,
 };
-
-const SVC: &str = "lin-kv";
+use crate::raft::state::RaftState;
+use crate::raft::storage;
+use crate::raft::tasks::tick;
+use crate::runtime::context::Context;
+use crate::runtime::dispatch::Dispatch;
+use crate::runtime::handler::Handler;
+use crate::runtime::rpc::RpcOptions;
+use crate::runtime::task::Task;
 
 pub fn log<M>(msg: &M)
 where
@@ -20,8 +28,12 @@ where
     stderr.flush().unwrap();
 }
 
-#[derive(Debug)]
+// Past it the forwarded request may or may not have been applied
+pub const FORWARD_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Map {
+    #[serde(with = "crate::crdt::wire::pairs")]
     map: HashMap<usize, usize>,
 }
 
@@ -34,7 +46,10 @@ impl Map {
         if let Some(value) = self.map.get(key) {
             Ok(SendPayload::ReadOk(ReadOkPayload::new(*value)))
         } else {
-            Err(ErrorPayload::new(20, format!("Key {:?} not found", key)))
+            Err(ErrorPayload::new(
+                ErrorCode::KeyDoesNotExist,
+                format!("Key {:?} not found", key),
+            ))
         }
     }
 
@@ -55,12 +70,15 @@ impl Map {
                 Ok(SendPayload::CasOk)
             } else {
                 Err(ErrorPayload::new(
-                    22,
+                    ErrorCode::PreconditionFailed,
                     format!("Value {:?} not equal to {:?}", value, from),
                 ))
             }
         } else {
-            Err(ErrorPayload::new(20, format!("Key {:?} not found", key)))
+            Err(ErrorPayload::new(
+                ErrorCode::KeyDoesNotExist,
+                format!("Key {:?} not found", key),
+            ))
         }
     }
 
@@ -72,101 +90,217 @@ impl Map {
     }
 }
 
+fn reply_error(ctx: &Context, request: &Message<()>, error: ErrorPayload) {
+    ctx.reply(request, SendPayload::Error(error));
+}
+
 #[derive(Debug)]
 pub struct Node {
-    pub node_id: String,
-    next_msg_id: RwLock<usize>,
+    pub ctx: Arc<Context>,
     map: RwLock<Map>,
-    lock: Mutex<()>,
+    pub raft: Arc<Mutex<RaftState>>,
+    // Client requests waiting for their log entry to be applied, by index,
+    // along with the term they were proposed in
+    pending: Mutex<HashMap<u64, (u64, Message<()>)>>,
 }
 
 impl Node {
-    pub fn new(node_id: String) -> Self {
-        Node {
-            node_id,
-            next_msg_id: RwLock::new(1),
-            map: RwLock::new(Map::new(HashMap::new())),
-            lock: Mutex::new(()),
+    pub fn build_body<P>(&self, payload: P, in_reply_to: Option<usize>) -> Body<P> {
+        self.ctx.build_body(payload, in_reply_to)
+    }
+
+    // Served by the leader, proxied to it from the other nodes
+    fn submit(&self, request: Message<()>, op: OpPayload) -> Result<(), ErrorPayload> {
+        let leader = self.raft.lock().unwrap().leader.clone();
+        match leader {
+            Some(leader) if leader == self.ctx.node_id => self.propose(request, op),
+            // Forwarded once at most, the leader may have changed meanwhile
+            Some(leader) if !self.ctx.node_ids.contains(&request.src) => {
+                self.forward(&leader, request, op);
+                Ok(())
+            }
+            _ => Err(ErrorPayload::new(
+                ErrorCode::TemporarilyUnavailable,
+                "No leader known".to_owned(),
+            )),
         }
     }
 
-    pub fn build_body<P>(&self, payload: P, in_reply_to: Option<usize>) -> Body<P>
-    where
-        P: PayloadTrait,
-    {
-        let body = Body::new(
-            payload,
-            Some(self.next_msg_id.read().unwrap().clone()),
-            in_reply_to,
+    // The leader's reply, or error, is relayed to the client as its own
+    fn forward(&self, leader: &str, request: Message<()>, op: OpPayload) {
+        self.ctx.log(&format!(
+            "Forwarding {:?} to {}",
+            request.body.msg_id, leader
+        ));
+        let ctx = Arc::clone(&self.ctx);
+        self.ctx.rpc(
+            leader,
+            op,
+            RpcOptions::new(FORWARD_TIMEOUT),
+            move |result| match result {
+                Ok(reply) => ctx.reply(&request, reply.body.payload),
+                Err(err) => reply_error(&ctx, &request, err),
+            },
         );
-        let mut nxt_msg_id_guard = self.next_msg_id.write().unwrap();
-        *nxt_msg_id_guard += 1;
-        body
     }
-}
 
-pub fn handle_op<O>(op_payload: O, node: &Arc<Node>) -> SendPayload
-where
-    O: OpPayloadTrait,
-{
-    let mut map_guard = node.map.write().unwrap();
-    let reply_payload = match map_guard.apply(op_payload) {
-        Ok(payload) => payload,
-        Err(err) => SendPayload::Error(err),
-    };
-    reply_payload
+    // Appends the op to the log, the client gets its reply once applied
+    fn propose(&self, request: Message<()>, op: OpPayload) -> Result<(), ErrorPayload> {
+        let mut state = self.raft.lock().unwrap();
+        match state.propose(op) {
+            Some(index) => {
+                let term = state.current_term;
+                self.pending.lock().unwrap().insert(index, (term, request));
+                Ok(())
+            }
+            None => Err(ErrorPayload::new(
+                ErrorCode::TemporarilyUnavailable,
+                format!("Not the leader, {:?} is", state.leader),
+            )),
+        }
+    }
+
+    // The map is locked before the log, so that it always matches
+    // `last_applied` when not locked
+    pub fn apply_committed(&self) {
+        let mut map_guard = self.map.write().unwrap();
+        let committed = self.raft.lock().unwrap().take_committed();
+        if committed.is_empty() {
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        for (index, entry) in committed {
+            let result = map_guard.apply(entry.op);
+            match pending.remove(&index) {
+                Some((term, request)) if term == entry.term => match result {
+                    Ok(payload) => self.ctx.reply(&request, payload),
+                    Err(err) => reply_error(&self.ctx, &request, err),
+                },
+                // Another leader's entry took the place of ours
+                Some((_, request)) => reply_error(
+                    &self.ctx,
+                    &request,
+                    ErrorPayload::new(
+                        ErrorCode::TemporarilyUnavailable,
+                        "Leadership lost before commit".to_owned(),
+                    ),
+                ),
+                None => (),
+            }
+        }
+    }
+
+    pub fn compact(&self) {
+        let map_guard = self.map.read().unwrap();
+        let mut state = self.raft.lock().unwrap();
+        if state.should_snapshot() {
+            state.compact(map_guard.clone());
+        }
+    }
+
+    fn install_snapshot(&self, snapshot_p: InstallSnapshotPayload) -> InstallSnapshotOkPayload {
+        let mut map_guard = self.map.write().unwrap();
+        let (reply, snapshot_opt) = self.raft.lock().unwrap().install_snapshot(snapshot_p);
+        if let Some(snapshot) = snapshot_opt {
+            let last_index = snapshot.last_index;
+            *map_guard = snapshot.map;
+
+            // Whether the ops still waiting were part of it is unknown
+            let mut pending = self.pending.lock().unwrap();
+            let applied: Vec<u64> = pending
+                .keys()
+                .filter(|&&index| index <= last_index)
+                .copied()
+                .collect();
+            applied.into_iter().for_each(|index| {
+                let (_, request) = pending.remove(&index).unwrap();
+                reply_error(
+                    &self.ctx,
+                    &request,
+                    ErrorPayload::new(
+                        ErrorCode::Timeout,
+                        "Superseded by a snapshot, outcome unknown".to_owned(),
+                    ),
+                );
+            });
+        }
+        reply
+    }
 }
 
-pub fn handle_msg(request: Message<ReqPayload>, node: Arc<Node>) -> Result<(), ()> {
-    let _lock = node.lock.lock().unwrap();
+impl Handler for Node {
+    type Payload = ReqPayload;
 
+    fn init(ctx: Arc<Context>) -> Self {
+        let raft = RaftState::new(
+            ctx.node_id.clone(),
+            ctx.neighbors(),
+            storage::from_env(&ctx.node_id),
+        );
+        // Entries past the snapshot are applied again once committed
+        let map = match &raft.snapshot {
+            Some(snapshot) => snapshot.map.clone(),
+            None => Map::new(HashMap::new()),
+        };
+        Node {
+            map: RwLock::new(map),
+            raft: Arc::new(Mutex::new(raft)),
+            pending: Mutex::new(HashMap::new()),
+            ctx,
+        }
+    }
+
+    fn handle(&self, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
+        handle_msg(request, self)
+    }
+
+    // Handlers hold the Raft state for a moment and never wait: client ops
+    // are replied to once the tick task applies them, forwards from an RPC
+    // callback
+    fn dispatch() -> Dispatch {
+        Dispatch::Sequential
+    }
+
+    fn tasks() -> Vec<Task<Self>> {
+        vec![Task::new("tick", 10, tick)]
+    }
+}
+
+pub fn handle_msg(request: Message<ReqPayload>, node: &Node) -> Result<(), ErrorPayload> {
     eprintln!("Body : {:?}", request.body);
+    let header = Message::new(
+        request.src.clone(),
+        request.dest.clone(),
+        request.body.clone_header(),
+    );
     let Body {
         payload: req_payload,
         msg_id: msg_id_opt,
         in_reply_to: _,
     } = request.body;
 
-    // send response
+    // Client ops are replied to once committed
     let reply_payload = match req_payload {
-        ReqPayload::Init(_) => Ok(SendPayload::InitOk),
-        ReqPayload::Error(err) => {
-            eprintln!("Error received: {:?}", err);
-            Err(())
-        }
-        ReqPayload::Read(op_p) => Ok(handle_op(op_p, &node)),
-        ReqPayload::Write(op_p) => Ok(handle_op(op_p, &node)),
-        ReqPayload::Cas(op_p) => Ok(handle_op(op_p, &node)),
-        /*(ReqPayload::ReadOk(value), _, Some(reply_to)) => {
-            eprintln!("read ok : {:?} to {:}", value, reply_to);
-            (None, None)
-        }
-        (ReqPayload::WriteOk, _, Some(reply_to)) => {
-            eprintln!("Promise write ok to: {:?}", reply_to);
-            (None, None)
-        }
-        (ReqPayload::CasOk, _, Some(reply_to)) => {
-            eprintln!("Promise cas ok to: {:?}", reply_to);
-            (None, None)
-        }
-        (ReqPayload::ErrorPayload(err), _, Some(reply_to)) => {
-            eprintln!("Linkv error: {:?} replying to: {:}", err, &reply_to);
-            (None, None)
-        }*/
+        ReqPayload::Error(err) => Err(err),
+        ReqPayload::Read(op_p) => return node.submit(header, OpPayload::Read(op_p)),
+        ReqPayload::Write(op_p) => return node.submit(header, OpPayload::Write(op_p)),
+        ReqPayload::Cas(op_p) => return node.submit(header, OpPayload::Cas(op_p)),
+        ReqPayload::RequestVote(vote_p) => Ok(SendPayload::RequestVoteOk(
+            node.raft.lock().unwrap().request_vote(&vote_p),
+        )),
+        ReqPayload::AppendEntries(append_p) => Ok(SendPayload::AppendEntriesOk(
+            node.raft.lock().unwrap().append_entries(append_p),
+        )),
+        ReqPayload::InstallSnapshot(snapshot_p) => Ok(SendPayload::InstallSnapshotOk(
+            node.install_snapshot(snapshot_p),
+        )),
     }?;
 
-    /*let reply_dest = match reply_payload {
-        SendPayload::ReadOk(_) => SVC.to_owned(),
-        SendPayload::WriteOk => SVC.to_owned(),
-        SendPayload::CasOk => SVC.to_owned(),
-        SendPayload::Error(_) => SVC.to_owned(),
-        SendPayload::InitOk => request.src,
-    };*/
-
     // Send reply
     let body = node.build_body(reply_payload, msg_id_opt);
-    log(&format!("Send {:?}", body));
-    Message::new(body, request.src, node.node_id.clone()).send();
+    let msg = node.ctx.build_msg(&request.src, body);
+    node.ctx.send_msg(&msg);
 
     Ok(())
 }
@@ -0,0 +1,581 @@
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::raft::log::{Log, LogEntry, Snapshot};
+use crate::raft::msg::{
+    AppendEntriesOkPayload, AppendEntriesPayload, InstallSnapshotOkPayload, InstallSnapshotPayload,
+    OpPayload, ReqPayload, RequestVoteOkPayload, RequestVotePayload,
+};
+use crate::raft::node::Map;
+use crate::raft::storage::{Record, Storage};
+use crate::runtime::env_usize;
+
+// A follower hearing nothing from a leader for a random time in this range
+// runs for election, randomized so that candidates rarely split the vote
+pub const ELECTION_TIMEOUT_MIN: Duration = Duration::from_millis(150);
+pub const ELECTION_TIMEOUT_MAX: Duration = Duration::from_millis(300);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(50);
+// Entries per append_entries
+pub const MAX_BATCH: usize = 128;
+pub const DEFAULT_SNAPSHOT_ENTRIES: usize = 1000;
+
+// RAFT_SNAPSHOT_ENTRIES applied entries are compacted into a snapshot
+pub fn snapshot_entries() -> usize {
+    env_usize("RAFT_SNAPSHOT_ENTRIES").map_or(DEFAULT_SNAPSHOT_ENTRIES, |n| n.max(1))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Follower,
+    // Polling the peers before running for election
+    PreCandidate,
+    Candidate,
+    Leader,
+}
+
+fn election_deadline() -> Instant {
+    let timeout = rand::thread_rng().gen_range(ELECTION_TIMEOUT_MIN..ELECTION_TIMEOUT_MAX);
+    Instant::now() + timeout
+}
+
+// RAFT_ELECTION=0 turns elections off: the first node by id leads for
+// good, in term 1
+fn static_leader(node_id: &str, peers: &HashSet<String>) -> Option<String> {
+    match env_usize("RAFT_ELECTION") {
+        Some(0) => peers
+            .iter()
+            .map(String::as_str)
+            .chain([node_id])
+            .min()
+            .map(str::to_owned),
+        _ => None,
+    }
+}
+
+// Consensus state of a node. `current_term`, `voted_for` and the log are
+// the persistent part, that must survive a restart for Raft to be safe:
+// each change reaches the storage before any message depending on it goes
+// out.
+#[derive(Debug)]
+pub struct RaftState {
+    pub node_id: String,
+    pub peers: HashSet<String>,
+    pub current_term: u64,
+    pub voted_for: Option<String>,
+    pub log: Log,
+    pub role: Role,
+    // Leader of the current term, once heard from
+    pub leader: Option<String>,
+    pub commit_index: u64,
+    pub last_applied: u64,
+    // Latest snapshot, what the log was compacted into
+    pub snapshot: Option<Snapshot>,
+    votes: HashSet<String>,
+    election_deadline: Instant,
+    // Last time the leader of the current term was heard from
+    leader_contact: Option<Instant>,
+    next_heartbeat: Instant,
+    elections: bool,
+    // Leader only: next entry to send to each peer, last one known to match
+    next_index: HashMap<String, u64>,
+    match_index: HashMap<String, u64>,
+    // Peers with an append_entries not answered yet
+    in_flight: HashSet<String>,
+    storage: Box<dyn Storage>,
+}
+
+impl RaftState {
+    // Restores whatever the storage holds from a previous run
+    pub fn new(node_id: String, peers: HashSet<String>, mut storage: Box<dyn Storage>) -> Self {
+        let records = storage.load().expect("Failed to load the Raft state");
+        let leader = static_leader(&node_id, &peers);
+        let mut state = RaftState {
+            node_id,
+            peers,
+            current_term: 0,
+            voted_for: None,
+            log: Log::default(),
+            role: Role::Follower,
+            leader: None,
+            commit_index: 0,
+            last_applied: 0,
+            snapshot: None,
+            votes: HashSet::new(),
+            election_deadline: election_deadline(),
+            leader_contact: None,
+            next_heartbeat: Instant::now(),
+            elections: leader.is_none(),
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            in_flight: HashSet::new(),
+            storage,
+        };
+        records.into_iter().for_each(|record| state.restore(record));
+        if state.current_term > 0 {
+            eprintln!(
+                "Restored term {}, log up to {}",
+                state.current_term,
+                state.log.last_index()
+            );
+        }
+
+        if let Some(leader) = leader {
+            state.current_term = state.current_term.max(1);
+            state.leader = Some(leader.clone());
+            if leader == state.node_id {
+                state.become_leader();
+            }
+        }
+        state
+    }
+
+    fn restore(&mut self, record: Record) {
+        match record {
+            Record::HardState { term, voted_for } => {
+                self.current_term = term;
+                self.voted_for = voted_for;
+            }
+            Record::Entry { index, entry } => self.log.put(index, entry),
+            Record::Snapshot(snapshot) => {
+                self.log.reset(snapshot.last_index, snapshot.last_term);
+                self.commit_index = snapshot.last_index;
+                self.last_applied = snapshot.last_index;
+                self.snapshot = Some(snapshot);
+            }
+        }
+    }
+
+    fn hard_state(&self) -> Record {
+        Record::HardState {
+            term: self.current_term,
+            voted_for: self.voted_for.clone(),
+        }
+    }
+
+    // Going on with state that did not reach the storage could break
+    // safety after a restart, better crash
+    fn persist(&mut self, records: &[Record]) {
+        self.storage
+            .append(records)
+            .expect("Failed to persist the Raft state");
+    }
+
+    // Replaces the stored state with the snapshot and the entries after it
+    fn persist_snapshot(&mut self) {
+        let mut records = vec![self.hard_state()];
+        records.extend(self.snapshot.clone().map(Record::Snapshot));
+        let base_index = self.log.base_index();
+        records.extend(
+            (base_index + 1..)
+                .zip(self.log.entries_from(base_index + 1, usize::MAX))
+                .map(|(index, entry)| Record::Entry { index, entry }),
+        );
+        self.storage
+            .rewrite(&records)
+            .expect("Failed to persist the Raft snapshot");
+    }
+
+    pub fn majority(&self) -> usize {
+        let cluster_size = self.peers.len() + 1;
+        cluster_size / 2 + 1
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role == Role::Leader
+    }
+
+    pub fn election_due(&self) -> bool {
+        self.elections && self.role != Role::Leader && Instant::now() >= self.election_deadline
+    }
+
+    // Schedules the next heartbeat when one is due
+    pub fn heartbeat_due(&mut self) -> bool {
+        let now = Instant::now();
+        if self.role != Role::Leader || now < self.next_heartbeat {
+            return false;
+        }
+        self.next_heartbeat = now + HEARTBEAT_INTERVAL;
+        true
+    }
+
+    // Any message from a later term turns us into a follower of that term.
+    // Returns whether it did.
+    pub fn observe_term(&mut self, term: u64) -> bool {
+        if term <= self.current_term {
+            return false;
+        }
+        self.current_term = term;
+        self.voted_for = None;
+        self.leader = None;
+        self.leader_contact = None;
+        self.persist(&[self.hard_state()]);
+        if self.role != Role::Follower {
+            eprintln!("Term {}: stepping down", term);
+            self.role = Role::Follower;
+            // A deposed leader's deadline is long past, give the new one a
+            // chance to be heard from first
+            self.election_deadline = election_deadline();
+        }
+        true
+    }
+
+    // While a leader is known to be alive, no pre-vote is granted: a node
+    // that could not hear from it, isolated by a partition, cannot raise its
+    // term then disrupt the cluster once back
+    fn leader_alive(&self) -> bool {
+        self.role == Role::Leader
+            || self
+                .leader_contact
+                .is_some_and(|contact| contact.elapsed() < ELECTION_TIMEOUT_MIN)
+    }
+
+    fn vote_request(&self, term: u64, pre_vote: bool) -> RequestVotePayload {
+        RequestVotePayload {
+            term,
+            candidate_id: self.node_id.clone(),
+            last_log_index: self.log.last_index(),
+            last_log_term: self.log.last_term(),
+            pre_vote,
+        }
+    }
+
+    fn heard_from_leader(&mut self, leader_id: String) {
+        self.role = Role::Follower;
+        self.leader = Some(leader_id);
+        self.leader_contact = Some(Instant::now());
+        self.election_deadline = election_deadline();
+    }
+
+    // Elections start with a pre-vote, the term only changes once a
+    // majority would vote for us
+    pub fn become_pre_candidate(&mut self) -> RequestVotePayload {
+        self.role = Role::PreCandidate;
+        self.leader = None;
+        self.votes = HashSet::from([self.node_id.clone()]);
+        self.election_deadline = election_deadline();
+        self.vote_request(self.current_term + 1, true)
+    }
+
+    // Returns the vote request to send once a majority granted its pre-vote
+    pub fn record_pre_vote(
+        &mut self,
+        term: u64,
+        from: String,
+        granted: bool,
+    ) -> Option<RequestVotePayload> {
+        if self.role != Role::PreCandidate || term != self.current_term + 1 || !granted {
+            return None;
+        }
+        self.votes.insert(from);
+        self.try_become_candidate()
+    }
+
+    // A single node cluster wins the pre-vote on its own
+    pub fn try_become_candidate(&mut self) -> Option<RequestVotePayload> {
+        if self.role != Role::PreCandidate || self.votes.len() < self.majority() {
+            return None;
+        }
+        Some(self.become_candidate())
+    }
+
+    pub fn become_candidate(&mut self) -> RequestVotePayload {
+        self.current_term += 1;
+        self.role = Role::Candidate;
+        self.voted_for = Some(self.node_id.clone());
+        self.leader = None;
+        self.votes = HashSet::from([self.node_id.clone()]);
+        self.election_deadline = election_deadline();
+        self.persist(&[self.hard_state()]);
+        eprintln!("Term {}: running for election", self.current_term);
+
+        self.vote_request(self.current_term, false)
+    }
+
+    // Returns whether the vote made us leader
+    pub fn record_vote(&mut self, term: u64, from: String, granted: bool) -> bool {
+        if self.role != Role::Candidate || term != self.current_term || !granted {
+            return false;
+        }
+        self.votes.insert(from);
+        self.try_become_leader()
+    }
+
+    // A single node cluster wins on its own vote
+    pub fn try_become_leader(&mut self) -> bool {
+        if self.role != Role::Candidate || self.votes.len() < self.majority() {
+            return false;
+        }
+        self.become_leader();
+        eprintln!("Term {}: elected leader", self.current_term);
+        true
+    }
+
+    fn become_leader(&mut self) {
+        self.role = Role::Leader;
+        self.leader = Some(self.node_id.clone());
+        self.next_heartbeat = Instant::now();
+        let next = self.log.last_index() + 1;
+        self.next_index = self.peers.iter().map(|p| (p.clone(), next)).collect();
+        self.match_index = self.peers.iter().map(|p| (p.clone(), 0)).collect();
+        self.in_flight.clear();
+    }
+
+    pub fn request_vote(&mut self, req: &RequestVotePayload) -> RequestVoteOkPayload {
+        // Only for candidates whose log holds every entry we have
+        let up_to_date = (req.last_log_term, req.last_log_index)
+            >= (self.log.last_term(), self.log.last_index());
+        if req.pre_vote {
+            return RequestVoteOkPayload {
+                term: self.current_term,
+                vote_granted: req.term > self.current_term && up_to_date && !self.leader_alive(),
+            };
+        }
+
+        self.observe_term(req.term);
+        let vote_granted = req.term == self.current_term
+            && up_to_date
+            && self
+                .voted_for
+                .as_ref()
+                .is_none_or(|voted| *voted == req.candidate_id);
+        if vote_granted {
+            if self.voted_for.is_none() {
+                self.voted_for = Some(req.candidate_id.clone());
+                self.persist(&[self.hard_state()]);
+            }
+            self.election_deadline = election_deadline();
+        }
+
+        RequestVoteOkPayload {
+            term: self.current_term,
+            vote_granted,
+        }
+    }
+
+    // Leader only, returns the index of the new entry
+    pub fn propose(&mut self, op: OpPayload) -> Option<u64> {
+        if !self.is_leader() {
+            return None;
+        }
+        let entry = LogEntry {
+            term: self.current_term,
+            op,
+        };
+        let index = self.log.append(entry.clone());
+        self.persist(&[Record::Entry { index, entry }]);
+        self.advance_commit();
+        Some(index)
+    }
+
+    // Peers to send entries to, a snapshot if already compacted, or a
+    // heartbeat. Marks them in flight.
+    pub fn append_targets(&mut self) -> Vec<(String, ReqPayload)> {
+        let heartbeat = self.heartbeat_due();
+        if !self.is_leader() {
+            return vec![];
+        }
+
+        let peers: Vec<String> = self
+            .peers
+            .iter()
+            .filter(|&p| !self.in_flight.contains(p))
+            .filter(|&p| heartbeat || self.next_index[p] <= self.log.last_index())
+            .cloned()
+            .collect();
+        peers
+            .into_iter()
+            .map(|peer| {
+                self.in_flight.insert(peer.clone());
+                let req = match &self.snapshot {
+                    Some(snapshot) if self.next_index[&peer] <= self.log.base_index() => {
+                        ReqPayload::InstallSnapshot(InstallSnapshotPayload {
+                            term: self.current_term,
+                            leader_id: self.node_id.clone(),
+                            snapshot: snapshot.clone(),
+                        })
+                    }
+                    _ => ReqPayload::AppendEntries(self.append_request(&peer)),
+                };
+                (peer, req)
+            })
+            .collect()
+    }
+
+    fn append_request(&self, peer: &str) -> AppendEntriesPayload {
+        let next = self.next_index[peer];
+        let prev_log_index = next - 1;
+        AppendEntriesPayload {
+            term: self.current_term,
+            leader_id: self.node_id.clone(),
+            prev_log_index,
+            prev_log_term: self.log.term_at(prev_log_index).unwrap_or(0),
+            entries: self.log.entries_from(next, MAX_BATCH),
+            leader_commit: self.commit_index,
+        }
+    }
+
+    pub fn append_entries(&mut self, mut req: AppendEntriesPayload) -> AppendEntriesOkPayload {
+        self.observe_term(req.term);
+
+        let mut reply = AppendEntriesOkPayload {
+            term: self.current_term,
+            success: false,
+            match_index: 0,
+        };
+        if req.term < self.current_term {
+            return reply;
+        }
+
+        // A candidate of the same term lost the election
+        self.heard_from_leader(req.leader_id.clone());
+
+        // Entries we compacted already are committed, hence match
+        let base_index = self.log.base_index();
+        if req.prev_log_index < base_index {
+            let skip = (base_index - req.prev_log_index) as usize;
+            req.entries.drain(..skip.min(req.entries.len()));
+            req.prev_log_index = base_index;
+            req.prev_log_term = self.log.term_at(base_index).unwrap();
+        }
+
+        // Consistency check: our log must hold the entry preceding the new
+        // ones, else the leader retries from further back
+        match self.log.term_at(req.prev_log_index) {
+            Some(term) if term == req.prev_log_term => {
+                let last_new = req.prev_log_index + req.entries.len() as u64;
+                let written: Vec<Record> = self
+                    .log
+                    .merge(req.prev_log_index, req.entries)
+                    .into_iter()
+                    .map(|(index, entry)| Record::Entry { index, entry })
+                    .collect();
+                self.persist(&written);
+                // A stale append, delivered late, knows less than we do:
+                // the commit index never goes back
+                self.commit_index = self.commit_index.max(req.leader_commit.min(last_new));
+                reply.success = true;
+                reply.match_index = last_new;
+            }
+            Some(_) => reply.match_index = req.prev_log_index - 1,
+            None => reply.match_index = self.log.last_index(),
+        }
+        reply
+    }
+
+    // None when the append failed to get an answer
+    pub fn append_reply(&mut self, peer: &str, reply: Option<AppendEntriesOkPayload>) {
+        self.in_flight.remove(peer);
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return,
+        };
+        if self.observe_term(reply.term) || !self.is_leader() || reply.term != self.current_term {
+            return;
+        }
+
+        if reply.success {
+            let matched = self.match_index.entry(peer.to_owned()).or_insert(0);
+            *matched = (*matched).max(reply.match_index);
+            let next = *matched + 1;
+            self.next_index.insert(peer.to_owned(), next);
+            self.advance_commit();
+        } else {
+            let next = self.next_index.entry(peer.to_owned()).or_insert(1);
+            *next = (reply.match_index + 1).min(*next - 1).max(1);
+        }
+    }
+
+    pub fn snapshot_reply(
+        &mut self,
+        peer: &str,
+        index: u64,
+        reply: Option<InstallSnapshotOkPayload>,
+    ) {
+        self.in_flight.remove(peer);
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return,
+        };
+        if self.observe_term(reply.term) || !self.is_leader() || reply.term != self.current_term {
+            return;
+        }
+
+        let matched = self.match_index.entry(peer.to_owned()).or_insert(0);
+        *matched = (*matched).max(index);
+        let next = *matched + 1;
+        self.next_index.insert(peer.to_owned(), next);
+        self.advance_commit();
+    }
+
+    // Returns the snapshot when the state machine must be replaced by it
+    pub fn install_snapshot(
+        &mut self,
+        req: InstallSnapshotPayload,
+    ) -> (InstallSnapshotOkPayload, Option<Snapshot>) {
+        self.observe_term(req.term);
+        let reply = InstallSnapshotOkPayload {
+            term: self.current_term,
+        };
+        if req.term < self.current_term {
+            return (reply, None);
+        }
+
+        self.heard_from_leader(req.leader_id);
+
+        let snapshot = req.snapshot;
+        if snapshot.last_index <= self.last_applied {
+            return (reply, None);
+        }
+        eprintln!("Installing snapshot up to {}", snapshot.last_index);
+        self.log.reset(snapshot.last_index, snapshot.last_term);
+        self.commit_index = self.commit_index.max(snapshot.last_index);
+        self.last_applied = snapshot.last_index;
+        self.snapshot = Some(snapshot.clone());
+        self.persist_snapshot();
+        (reply, Some(snapshot))
+    }
+
+    // Snapshot of the state machine at `last_applied`, when enough entries
+    // were applied since the previous one
+    pub fn should_snapshot(&self) -> bool {
+        self.last_applied - self.log.base_index() >= snapshot_entries() as u64
+    }
+
+    pub fn compact(&mut self, map: Map) {
+        let index = self.last_applied;
+        let snapshot = Snapshot {
+            last_index: index,
+            last_term: self.log.term_at(index).unwrap(),
+            map,
+        };
+        self.log.compact(index);
+        self.snapshot = Some(snapshot);
+        self.persist_snapshot();
+        eprintln!("Compacted the log up to {}", index);
+    }
+
+    // Commit the last entry of the current term a majority holds, and
+    // everything before it
+    fn advance_commit(&mut self) {
+        let mut matched: Vec<u64> = self.match_index.values().copied().collect();
+        matched.push(self.log.last_index());
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        let majority_index = matched[self.majority() - 1];
+
+        if majority_index > self.commit_index
+            && self.log.term_at(majority_index) == Some(self.current_term)
+        {
+            self.commit_index = majority_index;
+        }
+    }
+
+    // Committed entries not applied yet, in order
+    pub fn take_committed(&mut self) -> Vec<(u64, LogEntry)> {
+        let committed = (self.last_applied + 1..=self.commit_index)
+            .filter_map(|i| self.log.get(i).map(|entry| (i, entry.clone())))
+            .collect();
+        self.last_applied = self.last_applied.max(self.commit_index);
+        committed
+    }
+}
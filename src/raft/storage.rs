@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::raft::log::{LogEntry, Snapshot};
+
+// Past this size, records go to a new segment
+pub const DEFAULT_SEGMENT_BYTES: u64 = 1 << 20;
+
+// Durable change of the consensus state, replayed in order on restart
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum Record {
+    #[serde(rename = "hard_state")]
+    HardState {
+        term: u64,
+        voted_for: Option<String>,
+    },
+    // Replaces the entry at `index`, and drops any after it
+    #[serde(rename = "entry")]
+    Entry { index: u64, entry: LogEntry },
+    // Replaces the state machine and every entry up to its index
+    #[serde(rename = "snapshot")]
+    Snapshot(Snapshot),
+}
+
+pub trait Storage: Send + fmt::Debug {
+    // Every record stored by the previous runs, oldest first
+    fn load(&mut self) -> io::Result<Vec<Record>>;
+    // The records are durable once it returns
+    fn append(&mut self, records: &[Record]) -> io::Result<()>;
+    // Replaces everything stored with `records`
+    fn rewrite(&mut self, records: &[Record]) -> io::Result<()>;
+}
+
+// Files under RAFT_DATA_DIR/<node id> when set, nothing durable otherwise
+pub fn from_env(node_id: &str) -> Box<dyn Storage> {
+    match env::var("RAFT_DATA_DIR") {
+        Ok(dir) => {
+            let dir = Path::new(&dir).join(node_id);
+            let storage = FileStorage::open(&dir)
+                .unwrap_or_else(|e| panic!("Cannot open {}: {}", dir.display(), e));
+            Box::new(storage)
+        }
+        Err(_) => Box::new(MemoryStorage),
+    }
+}
+
+// Nothing survives the process
+#[derive(Debug, Default)]
+pub struct MemoryStorage;
+
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> io::Result<Vec<Record>> {
+        Ok(vec![])
+    }
+
+    fn append(&mut self, _records: &[Record]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rewrite(&mut self, _records: &[Record]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// CRC-32 (IEEE), computed bitwise: records are small
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// Record framing: length and checksum of the JSON payload, little endian
+fn frame(record: &Record) -> Vec<u8> {
+    let payload = serde_json::to_vec(record).unwrap();
+    let mut bytes = Vec::with_capacity(payload.len() + 8);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+// Records of a segment, along with the length of its valid prefix
+fn unframe(bytes: &[u8]) -> (Vec<Record>, usize) {
+    let mut records = vec![];
+    let mut offset = 0;
+    while bytes.len() - offset >= 8 {
+        let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+        let payload = match bytes.get(offset + 8..offset + 8 + len) {
+            Some(payload) if crc32(payload) == crc => payload,
+            _ => break,
+        };
+        match serde_json::from_slice(payload) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+        offset += 8 + len;
+    }
+    (records, offset)
+}
+
+// Append-only segments in a directory, each record framed with a checksum
+// and fsynced before returning. A torn record at the end of the last
+// segment, from a crash mid-write, is cut off on load.
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+    segment_id: u64,
+    segment: Option<File>,
+    segment_bytes: u64,
+}
+
+impl FileStorage {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut storage = FileStorage {
+            dir: dir.to_owned(),
+            segment_id: 0,
+            segment: None,
+            segment_bytes: DEFAULT_SEGMENT_BYTES,
+        };
+        storage.segment_id = storage.segment_ids()?.last().copied().unwrap_or(0);
+        Ok(storage)
+    }
+
+    pub fn with_segment_bytes(self, segment_bytes: u64) -> Self {
+        FileStorage {
+            segment_bytes,
+            ..self
+        }
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("segment-{:08}.log", id))
+    }
+
+    fn segment_ids(&self) -> io::Result<Vec<u64>> {
+        let mut ids: Vec<u64> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix("segment-")?
+                    .strip_suffix(".log")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    // Makes created and deleted segments durable
+    fn sync_dir(&self) -> io::Result<()> {
+        File::open(&self.dir)?.sync_all()
+    }
+
+    fn open_segment(&mut self, id: u64) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.segment_path(id))?;
+        self.segment_id = id;
+        self.segment = Some(file);
+        self.sync_dir()
+    }
+
+    fn write(&mut self, records: &[Record]) -> io::Result<()> {
+        let full = match &self.segment {
+            Some(file) => file.metadata()?.len() >= self.segment_bytes,
+            None => true,
+        };
+        if full {
+            self.open_segment(self.segment_id + 1)?;
+        }
+
+        let bytes: Vec<u8> = records.iter().flat_map(frame).collect();
+        let file = self.segment.as_mut().unwrap();
+        file.write_all(&bytes)?;
+        file.sync_data()
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&mut self) -> io::Result<Vec<Record>> {
+        let ids = self.segment_ids()?;
+        let mut records = vec![];
+        for (i, &id) in ids.iter().enumerate() {
+            let path = self.segment_path(id);
+            let mut bytes = vec![];
+            File::open(&path)?.read_to_end(&mut bytes)?;
+
+            let (segment_records, valid) = unframe(&bytes);
+            if valid < bytes.len() {
+                if i + 1 < ids.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Corrupted segment {}", path.display()),
+                    ));
+                }
+                eprintln!("Dropping a torn record at the end of {}", path.display());
+                let file = OpenOptions::new().write(true).open(&path)?;
+                file.set_len(valid as u64)?;
+                file.sync_all()?;
+            }
+            records.extend(segment_records);
+        }
+        Ok(records)
+    }
+
+    fn append(&mut self, records: &[Record]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.write(records)
+    }
+
+    // The new segment is durable before the older ones go, a crash in
+    // between replays both, to the same state
+    fn rewrite(&mut self, records: &[Record]) -> io::Result<()> {
+        let previous = self.segment_ids()?;
+        self.open_segment(self.segment_id + 1)?;
+        self.write(records)?;
+        for id in previous {
+            fs::remove_file(self.segment_path(id))?;
+        }
+        self.sync_dir()
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::{Arc, Mutex};
+
+use crate::protocol::Message;
+use crate::raft::msg::{ReqPayload, RequestVotePayload, SendPayload};
+use crate::raft::node::Node;
+use crate::raft::state::{RaftState, HEARTBEAT_INTERVAL};
+use crate::runtime::context::Context;
+use crate::runtime::rpc::{RpcOptions, RpcResult};
+
+fn reply_payload(result: RpcResult) -> Option<SendPayload> {
+    result
+        .and_then(Message::into_typed::<SendPayload>)
+        .ok()
+        .map(|msg| msg.body.payload)
+}
+
+// Runs elections on followers, replicates the log from the leader, applies
+// what got committed and compacts the log. Applying and compacting both
+// happen here only, one after the other.
+pub fn tick(node: &Node) {
+    let mut state = node.raft.lock().unwrap();
+    if state.election_due() {
+        let pre_vote = state.become_pre_candidate();
+        let req = match state.try_become_candidate() {
+            Some(req) => {
+                state.try_become_leader();
+                req
+            }
+            None => pre_vote,
+        };
+        drop(state);
+        request_votes(&node.ctx, &node.raft, req);
+    } else {
+        drop(state);
+    }
+
+    replicate(&node.ctx, &node.raft);
+    node.apply_committed();
+    node.compact();
+}
+
+// Pre-votes first, then the votes themselves once a majority granted them
+pub fn request_votes(ctx: &Arc<Context>, raft: &Arc<Mutex<RaftState>>, req: RequestVotePayload) {
+    let (term, pre_vote) = (req.term, req.pre_vote);
+    let peers = raft.lock().unwrap().peers.clone();
+    peers.into_iter().for_each(|peer| {
+        let ctx_clone = Arc::clone(ctx);
+        let raft_clone = Arc::clone(raft);
+        let payload = ReqPayload::RequestVote(req.clone());
+        let from = peer.clone();
+        ctx.rpc(
+            &peer,
+            payload,
+            RpcOptions::new(HEARTBEAT_INTERVAL * 2),
+            move |result| {
+                if let Some(SendPayload::RequestVoteOk(vote)) = reply_payload(result) {
+                    let mut state = raft_clone.lock().unwrap();
+                    if state.observe_term(vote.term) {
+                        return;
+                    }
+                    if pre_vote {
+                        let req = state.record_pre_vote(term, from, vote.vote_granted);
+                        drop(state);
+                        if let Some(req) = req {
+                            request_votes(&ctx_clone, &raft_clone, req);
+                        }
+                        return;
+                    }
+                    let won = state.record_vote(vote.term, from, vote.vote_granted);
+                    drop(state);
+                    if won {
+                        replicate(&ctx_clone, &raft_clone);
+                    }
+                }
+            },
+        );
+    });
+}
+
+// One append_entries or install_snapshot in flight per peer at most,
+// carrying what it misses, or nothing as a heartbeat
+pub fn replicate(ctx: &Arc<Context>, raft: &Arc<Mutex<RaftState>>) {
+    let targets = raft.lock().unwrap().append_targets();
+    targets.into_iter().for_each(|(peer, req)| {
+        send_replication(ctx, raft, peer, req);
+    });
+}
+
+fn send_replication(
+    ctx: &Arc<Context>,
+    raft: &Arc<Mutex<RaftState>>,
+    peer: String,
+    req: ReqPayload,
+) {
+    let snapshot_index = match &req {
+        ReqPayload::InstallSnapshot(snapshot_p) => Some(snapshot_p.snapshot.last_index),
+        _ => None,
+    };
+    let raft_clone = Arc::clone(raft);
+    let dest = peer.clone();
+    ctx.rpc(
+        &peer,
+        req,
+        RpcOptions::new(HEARTBEAT_INTERVAL * 2),
+        move |result| {
+            let reply = reply_payload(result);
+            let mut state = raft_clone.lock().unwrap();
+            match (snapshot_index, reply) {
+                (Some(index), Some(SendPayload::InstallSnapshotOk(ok))) => {
+                    state.snapshot_reply(&dest, index, Some(ok))
+                }
+                (Some(index), _) => state.snapshot_reply(&dest, index, None),
+                (None, Some(SendPayload::AppendEntriesOk(ok))) => {
+                    state.append_reply(&dest, Some(ok))
+                }
+                (None, _) => state.append_reply(&dest, None),
+            }
+        },
+    );
+}
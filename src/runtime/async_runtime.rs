@@ -0,0 +1,191 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines};
+use tokio::sync::oneshot;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::protocol::{ErrorCode, ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::rpc::RpcOptions;
+use crate::runtime::scheduler::wait;
+use crate::runtime::task::Task;
+use crate::runtime::{init_context, reply_error, MAX_READ_ERRORS};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+pub type AsyncTaskFn<H> = fn(Arc<H>) -> BoxFuture<()>;
+
+// Configured as any `Task`: name, jitter and the <NAME>_INTERVAL override,
+// e.g. `AsyncTask::with_callback("gossip", 100, gossip).with_jitter(0.1)`
+pub type AsyncTask<H> = Task<H, AsyncTaskFn<H>>;
+
+impl Context {
+    // Same as `sync_rpc`, without blocking the executor thread. The
+    // completion callback runs where the RpcClient completes the RPC: on the
+    // stdin reader for a reply, on the RpcClient timer thread for a timeout,
+    // never on the executor. It only hands the result over to the future.
+    pub async fn async_rpc<P, R>(
+        &self,
+        dest: &str,
+        payload: P,
+        opts: RpcOptions,
+    ) -> Result<Message<R>, ErrorPayload>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.rpc(dest, payload, opts, move |result| {
+            let _ = tx.send(result);
+        });
+
+        match rx.await {
+            Ok(result) => result?.into_typed(),
+            Err(_) => Err(ErrorPayload::new(
+                ErrorCode::Crash,
+                "RPC dropped without completion".to_owned(),
+            )),
+        }
+    }
+}
+
+pub trait AsyncHandler: Sized + Send + Sync + 'static {
+    type Payload: DeserializeOwned + Send + 'static;
+
+    // Called once the init handshake is done, init_ok is already sent
+    fn init(ctx: Arc<Context>) -> Self;
+
+    // Each request runs in its own task, awaiting an RPC does not block others
+    fn handle(
+        self: Arc<Self>,
+        request: Message<Self::Payload>,
+    ) -> impl Future<Output = Result<(), ErrorPayload>> + Send;
+
+    fn tasks() -> Vec<AsyncTask<Self>> {
+        vec![]
+    }
+}
+
+// Same as `runtime::read_line`, errors included
+async fn next_line<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> Option<String> {
+    let mut errors = 0;
+    loop {
+        match lines.next_line().await {
+            Ok(input) => return input,
+            Err(e) => {
+                eprintln!("Error reading from stdin: {}", e);
+                errors += 1;
+                if errors >= MAX_READ_ERRORS {
+                    eprintln!("Giving up on stdin after {} errors", errors);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+// Runs `task` on the executor of the caller until aborted
+pub fn spawn_task<H>(node: &Arc<H>, task: AsyncTask<H>) -> JoinHandle<()>
+where
+    H: Send + Sync + 'static,
+{
+    let task = task.with_env();
+    let node = Arc::clone(node);
+    eprintln!("Starting task {} every {}ms", task.name, task.millisec);
+    tokio::spawn(async move {
+        loop {
+            (task.callback)(Arc::clone(&node)).await;
+            tokio::time::sleep(wait(task.millisec, task.jitter)).await;
+        }
+    })
+}
+
+async fn serve<H: AsyncHandler>() {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    // Loop endlessly until init
+    let ctx = loop {
+        let input = match next_line(&mut lines).await {
+            Some(input) => input,
+            None => return,
+        };
+        eprintln!("Read msg: {}", input);
+        if let Some(ctx) = init_context(input.trim()) {
+            break Arc::new(ctx);
+        }
+    };
+
+    let node = Arc::new(H::init(Arc::clone(&ctx)));
+
+    // Periodic tasks share the executor with the handlers
+    H::tasks().into_iter().for_each(|task| {
+        spawn_task(&node, task);
+    });
+
+    let mut handlers = JoinSet::new();
+    loop {
+        // Reap the handlers already done
+        while handlers.try_join_next().is_some() {}
+
+        let input = match next_line(&mut lines).await {
+            Some(input) => input,
+            None => break,
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        eprintln!("Read msg: {}", input);
+
+        let raw = match serde_json::from_str::<Message<Value>>(&input) {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("Error parsing message: {}", e);
+                continue;
+            }
+        };
+
+        let raw = match ctx.resolve(raw) {
+            Some(raw) => raw,
+            None => continue,
+        };
+
+        // Requests the workload does not know get an error back, not silence
+        let header = Message::new(raw.src.clone(), raw.dest.clone(), raw.body.clone_header());
+        let request = match raw.into_typed::<H::Payload>() {
+            Ok(request) => request,
+            Err(e) => {
+                reply_error(&ctx, &header, e);
+                continue;
+            }
+        };
+
+        let node_clone = Arc::clone(&node);
+        let ctx_clone = Arc::clone(&ctx);
+        handlers.spawn(async move {
+            let header = Message::new(
+                request.src.clone(),
+                request.dest.clone(),
+                request.body.clone_header(),
+            );
+            if let Err(e) = node_clone.handle(request).await {
+                reply_error(&ctx_clone, &header, e);
+            }
+        });
+    }
+
+    // Let the handlers in flight finish once stdin is closed
+    while handlers.join_next().await.is_some() {}
+}
+
+// Same contract as `runtime::run`, on a multi-threaded tokio executor
+pub fn run_async<H: AsyncHandler>() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build the async runtime");
+
+    runtime.block_on(serve::<H>());
+}
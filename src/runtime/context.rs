@@ -0,0 +1,141 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+use crate::output::{to_stderr, to_stdout};
+use crate::protocol::{Body, ErrorCode, ErrorPayload, Message};
+use crate::runtime::rpc::{RpcClient, RpcOptions, RpcResult, RpcStats, Sink};
+use crate::runtime::scheduler::Scheduler;
+
+// Node identity and outgoing side of the network, shared by the runtime and
+// the handler it drives.
+#[derive(Debug)]
+pub struct Context {
+    pub node_id: String,
+    pub node_ids: HashSet<String>,
+    next_msg_id: AtomicUsize,
+    rpc_client: RpcClient,
+    pub scheduler: Scheduler,
+}
+
+impl Context {
+    pub fn new(node_id: String, node_ids: HashSet<String>) -> Self {
+        Context::with_rpc_sink(node_id, node_ids, Arc::new(to_stdout))
+    }
+
+    // RPC requests go to `sink` instead of stdout, replies and other
+    // messages still go to stdout
+    pub fn with_rpc_sink(node_id: String, node_ids: HashSet<String>, sink: Sink) -> Self {
+        Context {
+            node_id,
+            node_ids,
+            next_msg_id: AtomicUsize::new(0),
+            rpc_client: RpcClient::new(sink),
+            scheduler: Scheduler::default(),
+        }
+    }
+
+    // All the other nodes of the cluster
+    pub fn neighbors(&self) -> HashSet<String> {
+        self.node_ids
+            .iter()
+            .filter(|&n| *n != self.node_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn next_msg_id(&self) -> usize {
+        self.next_msg_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub fn build_body<P>(&self, payload: P, in_reply_to: Option<usize>) -> Body<P> {
+        Body::new(payload, Some(self.next_msg_id()), in_reply_to)
+    }
+
+    pub fn build_msg<P>(&self, dest: &str, body: Body<P>) -> Message<P> {
+        Message::new(self.node_id.clone(), dest.to_owned(), body)
+    }
+
+    // Send a new request, returns its msg_id
+    pub fn send<P>(&self, dest: &str, payload: P) -> usize
+    where
+        P: Serialize,
+    {
+        let msg = self.build_msg(dest, self.build_body(payload, None));
+        self.send_msg(&msg);
+        msg.body.msg_id.unwrap()
+    }
+
+    pub fn reply<P, Q>(&self, request: &Message<Q>, payload: P)
+    where
+        P: Serialize,
+    {
+        let body = self.build_body(payload, request.body.msg_id);
+        self.send_msg(&self.build_msg(&request.src, body));
+    }
+
+    // Send a request, `callback` gets its reply, an error reply, or a
+    // timeout once `opts` gives up. Returns the request msg_id.
+    pub fn rpc<P, F>(&self, dest: &str, payload: P, opts: RpcOptions, callback: F) -> usize
+    where
+        P: Serialize,
+        F: FnOnce(RpcResult) + Send + 'static,
+    {
+        let payload = serde_json::to_value(payload).expect("Payload is not serializable");
+        let msg = self.build_msg(dest, self.build_body(payload, None));
+        let msg_id = msg.body.msg_id.unwrap();
+        self.rpc_client.call(msg, opts, Box::new(callback));
+        msg_id
+    }
+
+    // Blocks the calling thread until the RPC completes
+    pub fn sync_rpc<P, R>(
+        &self,
+        dest: &str,
+        payload: P,
+        opts: RpcOptions,
+    ) -> Result<Message<R>, ErrorPayload>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let (tx, rx) = mpsc::channel();
+        self.rpc(dest, payload, opts, move |result| {
+            let _ = tx.send(result);
+        });
+
+        match rx.recv() {
+            Ok(result) => result?.into_typed(),
+            Err(_) => Err(ErrorPayload::new(
+                ErrorCode::Crash,
+                "RPC dropped without completion".to_owned(),
+            )),
+        }
+    }
+
+    // Completes the pending RPC `reply` answers, or hands it back
+    pub fn resolve(&self, reply: Message<Value>) -> Option<Message<Value>> {
+        self.rpc_client.resolve(reply)
+    }
+
+    pub fn rpc_stats(&self) -> RpcStats {
+        self.rpc_client.stats()
+    }
+
+    pub fn send_msg<P>(&self, msg: &Message<P>)
+    where
+        P: Serialize,
+    {
+        to_stdout(msg);
+    }
+
+    pub fn log<M>(&self, msg: &M)
+    where
+        M: Serialize,
+    {
+        to_stderr(msg);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::runtime::env_usize;
+
+pub const DEFAULT_WORKERS: usize = 8;
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+// How the runtime runs the handler on incoming messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    // Every message handled in order on the dispatching thread
+    Sequential,
+    // Messages handled by a fixed set of workers fed by a bounded queue
+    Pool { workers: usize, capacity: usize },
+}
+
+impl Default for Dispatch {
+    fn default() -> Self {
+        Dispatch::Pool {
+            workers: DEFAULT_WORKERS,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+impl Dispatch {
+    // WORKERS / QUEUE_CAPACITY resize the pool of a handler that asked for
+    // one. WORKERS=0 forces sequential dispatch, the only override of a
+    // handler's choice: one that asked for sequential dispatch relies on
+    // messages being handled in order.
+    pub fn with_env(self) -> Self {
+        let (workers, capacity) = match self {
+            Dispatch::Sequential => return Dispatch::Sequential,
+            Dispatch::Pool { workers, capacity } => (workers, capacity),
+        };
+
+        match env_usize("WORKERS").unwrap_or(workers) {
+            0 => Dispatch::Sequential,
+            workers => Dispatch::Pool {
+                workers,
+                capacity: env_usize("QUEUE_CAPACITY").unwrap_or(capacity),
+            },
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+use crate::protocol::{ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::dispatch::Dispatch;
+use crate::runtime::task::Task;
+
+pub trait Handler: Sized + Send + Sync + 'static {
+    type Payload: DeserializeOwned + Send + 'static;
+
+    // Called once the init handshake is done, init_ok is already sent
+    fn init(ctx: Arc<Context>) -> Self;
+
+    // An error is sent back to the requester, if the request expects a reply
+    fn handle(&self, request: Message<Self::Payload>) -> Result<(), ErrorPayload>;
+
+    fn tasks() -> Vec<Task<Self>> {
+        vec![]
+    }
+
+    // Pooled by default, handlers relying on message order go sequential
+    fn dispatch() -> Dispatch {
+        Dispatch::default()
+    }
+}
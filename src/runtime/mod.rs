@@ -0,0 +1,179 @@
+#[cfg(feature = "async")]
+pub mod async_runtime;
+pub mod context;
+pub mod dispatch;
+pub mod handler;
+pub mod pool;
+pub mod rpc;
+pub mod scheduler;
+pub mod task;
+
+use serde_json::Value;
+use std::env;
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+use crate::protocol::{ErrorPayload, ErrorReplyPayload, InitOkPayload, InitReqPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::dispatch::Dispatch;
+use crate::runtime::handler::Handler;
+use crate::runtime::pool::WorkerPool;
+
+// Runtime knobs are read from the environment, Maelstrom passes it through
+pub fn env_usize(key: &str) -> Option<usize> {
+    env::var(key).ok().and_then(|value| value.parse().ok())
+}
+
+// Past this many errors in a row stdin is considered gone
+pub(crate) const MAX_READ_ERRORS: usize = 10;
+
+// None once stdin is closed. A line that fails to read, e.g. invalid UTF-8,
+// is skipped, but an error that keeps coming back ends the node instead of
+// spinning on it.
+fn read_line<R: BufRead>(reader: &mut R) -> Option<String> {
+    let mut errors = 0;
+    loop {
+        let mut input = String::new();
+        match reader.read_line(&mut input) {
+            Ok(0) => return None,
+            Ok(_) => return Some(input.trim().to_string()),
+            Err(e) => {
+                eprintln!("Error reading from stdin: {}", e);
+                errors += 1;
+                if errors >= MAX_READ_ERRORS {
+                    eprintln!("Giving up on stdin after {} errors", errors);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn init_context(input: &str) -> Option<Context> {
+    match serde_json::from_str::<Message<InitReqPayload>>(input) {
+        Ok(msg) => {
+            let InitReqPayload::Init(init_p) = &msg.body.payload;
+            let ctx = Context::new(init_p.node_id.clone(), init_p.node_ids.clone());
+            ctx.reply(&msg, InitOkPayload::InitOk);
+
+            Some(ctx)
+        }
+        Err(e) => {
+            eprintln!("Error parsing init message: {}", e);
+            None
+        }
+    }
+}
+
+fn init_loop<R: BufRead>(reader: &mut R) -> Option<Context> {
+    // Loop endlessly until init
+    while let Some(input) = read_line(reader) {
+        if input.is_empty() {
+            continue;
+        }
+        eprintln!("Read msg: {}", input);
+
+        if let Some(ctx) = init_context(&input) {
+            return Some(ctx);
+        }
+    }
+    None
+}
+
+// Reply with the error unless the request was itself a reply
+pub(crate) fn reply_error<P>(ctx: &Context, request: &Message<P>, error: ErrorPayload) {
+    eprintln!("Error handling message: {}", error);
+
+    if let (Some(msg_id), None) = (request.body.msg_id, request.body.in_reply_to) {
+        let body = ctx.build_body(ErrorReplyPayload::Error(error), Some(msg_id));
+        ctx.send_msg(&ctx.build_msg(&request.src, body));
+    }
+}
+
+fn handle<H: Handler>(node: &H, ctx: &Context, request: Message<H::Payload>) {
+    let header = Message::new(
+        request.src.clone(),
+        request.dest.clone(),
+        request.body.clone_header(),
+    );
+
+    if let Err(e) = node.handle(request) {
+        reply_error(ctx, &header, e);
+    }
+}
+
+fn dispatch<H: Handler>(node: &Arc<H>, ctx: &Arc<Context>, pool: Option<&WorkerPool>, input: &str) {
+    let raw = match serde_json::from_str::<Message<Value>>(input) {
+        Ok(raw) => raw,
+        Err(e) => {
+            eprintln!("Error parsing message: {}", e);
+            return;
+        }
+    };
+
+    // Replies to our own RPCs never reach the handler
+    let raw = match ctx.resolve(raw) {
+        Some(raw) => raw,
+        None => return,
+    };
+
+    // Requests the workload does not know get an error back, not silence
+    let header = Message::new(raw.src.clone(), raw.dest.clone(), raw.body.clone_header());
+    let request = match raw.into_typed::<H::Payload>() {
+        Ok(request) => request,
+        Err(e) => {
+            reply_error(ctx, &header, e);
+            return;
+        }
+    };
+
+    match pool {
+        // Replies only complete work already in progress: handling them
+        // inline keeps workers blocked on an RPC from starving on them.
+        Some(pool) if request.body.in_reply_to.is_none() => {
+            let node_clone = Arc::clone(node);
+            let ctx_clone = Arc::clone(ctx);
+            pool.execute(move || handle(&*node_clone, &ctx_clone, request));
+        }
+        _ => handle(&**node, ctx, request),
+    }
+}
+
+// Owns stdin, the init handshake and the periodic tasks of a node: every
+// workload binary boils down to `runtime::run::<Node>()`.
+pub fn run<H: Handler>() {
+    let stdin = io::stdin();
+    let mut stdin_lock = stdin.lock();
+
+    let ctx = match init_loop(&mut stdin_lock) {
+        Some(ctx) => Arc::new(ctx),
+        None => return,
+    };
+
+    let node = Arc::new(H::init(Arc::clone(&ctx)));
+
+    H::tasks()
+        .into_iter()
+        .for_each(|task| ctx.scheduler.start(&node, task.with_env()));
+
+    let dispatch_mode = H::dispatch().with_env();
+    ctx.log(&format!("Dispatch: {:?}", dispatch_mode));
+    let pool = match dispatch_mode {
+        Dispatch::Sequential => None,
+        Dispatch::Pool { workers, capacity } => Some(WorkerPool::new(workers, capacity)),
+    };
+
+    // Read stdin until closed, a full pool queue blocks the reading
+    while let Some(input) = read_line(&mut stdin_lock) {
+        if input.is_empty() {
+            continue;
+        }
+        eprintln!("Read msg: {}", input);
+
+        dispatch(&node, &ctx, pool.as_ref(), &input);
+    }
+
+    // Dropping the pool waits for the queued messages to be handled
+    drop(pool);
+    ctx.scheduler.stop_all();
+}
@@ -0,0 +1,57 @@
+use crossbeam::channel::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Fixed number of worker threads fed by a bounded queue: `execute` blocks
+// once the queue is full, which pushes back on the stdin reader.
+#[derive(Debug)]
+pub struct WorkerPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let (sender, receiver): (Sender<Job>, Receiver<Job>) = channel::bounded(capacity);
+
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || {
+                    while let Ok(job) = receiver.recv() {
+                        job();
+                    }
+                })
+            })
+            .collect();
+
+        WorkerPool {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(sender) = &self.sender {
+            if sender.send(Box::new(job)).is_err() {
+                eprintln!("Worker pool is shut down, dropping job");
+            }
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the queue lets the workers drain it and exit
+        self.sender.take();
+        self.workers.drain(..).for_each(|t_handle| {
+            if t_handle.join().is_err() {
+                eprintln!("Worker thread panicked");
+            }
+        });
+    }
+}
@@ -0,0 +1,290 @@
+use rand::Rng;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::output::to_stdout;
+use crate::protocol::{ErrorCode, ErrorPayload, ErrorReplyPayload, Message};
+
+pub type RpcResult = Result<Message<Value>, ErrorPayload>;
+pub type Callback = Box<dyn FnOnce(RpcResult) + Send>;
+// Where requests, and their retries, are written: stdout on a node
+pub type Sink = Arc<dyn Fn(&Message<Value>) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // Total number of sends, None keeps retrying until a reply comes back
+    pub max_attempts: Option<usize>,
+    // Each retry waits `multiplier` times longer than the previous one ...
+    pub multiplier: u32,
+    // ... up to this cap
+    pub max_timeout: Duration,
+    // Up to this fraction of the wait is added at random
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: Some(1),
+            multiplier: 1,
+            max_timeout: Duration::ZERO,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn exponential(max_attempts: Option<usize>, max_timeout: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            multiplier: 2,
+            max_timeout,
+            jitter: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RpcOptions {
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl RpcOptions {
+    pub fn new(timeout: Duration) -> Self {
+        RpcOptions {
+            timeout,
+            retry: RetryPolicy::none(),
+        }
+    }
+
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        RpcOptions { retry, ..self }
+    }
+
+    fn can_retry(&self, attempts: usize) -> bool {
+        self.retry.max_attempts.is_none_or(|max| attempts < max)
+    }
+
+    // How long to wait for a reply to the given attempt (starting at 1)
+    fn wait(&self, attempt: usize) -> Duration {
+        let exp = (attempt.saturating_sub(1)).min(16) as u32;
+        let mut wait = self
+            .timeout
+            .saturating_mul(self.retry.multiplier.max(1).saturating_pow(exp));
+        if attempt > 1 {
+            wait = wait.min(self.retry.max_timeout.max(self.timeout));
+        }
+        if self.retry.jitter > 0.0 {
+            let jitter = rand::thread_rng().gen_range(0.0..=self.retry.jitter);
+            wait += wait.mul_f64(jitter);
+        }
+        wait
+    }
+}
+
+struct Pending {
+    msg: Message<Value>,
+    opts: RpcOptions,
+    attempts: usize,
+    deadline: Instant,
+    callback: Callback,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RpcStats {
+    // Waiting for a reply
+    pub pending: usize,
+    // Resends so far, all RPCs together
+    pub retries: usize,
+    // Gave up on after their last attempt timed out
+    pub abandoned: usize,
+}
+
+#[derive(Default)]
+struct State {
+    pending: HashMap<usize, Pending>,
+    retries: usize,
+    abandoned: usize,
+    // Min-heap of deadlines, entries whose deadline moved are skipped
+    deadlines: BinaryHeap<Reverse<(Instant, usize)>>,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cvar: Condvar,
+    sink: Sink,
+}
+
+// Request/reply correlation by msg_id: replies are matched through
+// `resolve`, unanswered requests are resent by a timer thread until their
+// retry policy runs out, then fail with a timeout error.
+pub struct RpcClient {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for RpcClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RpcClient")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Default for RpcClient {
+    fn default() -> Self {
+        RpcClient::new(Arc::new(to_stdout))
+    }
+}
+
+impl Drop for RpcClient {
+    fn drop(&mut self) {
+        self.lock().shutdown = true;
+        self.shared.cvar.notify_one();
+    }
+}
+
+impl RpcClient {
+    pub fn new(sink: Sink) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            cvar: Condvar::new(),
+            sink,
+        });
+
+        let shared_timer = Arc::clone(&shared);
+        thread::spawn(move || timer_loop(&shared_timer));
+
+        RpcClient { shared }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    // `msg` must carry a msg_id, the callback runs exactly once
+    pub fn call(&self, msg: Message<Value>, opts: RpcOptions, callback: Callback) {
+        let msg_id = msg.body.msg_id.expect("RPC without msg_id");
+        let deadline = Instant::now() + opts.wait(1);
+        let out = msg.clone();
+
+        // Registered before sending, so that the reply cannot outrun it
+        let mut state = self.lock();
+        state.deadlines.push(Reverse((deadline, msg_id)));
+        state.pending.insert(
+            msg_id,
+            Pending {
+                msg,
+                opts,
+                attempts: 1,
+                deadline,
+                callback,
+            },
+        );
+        drop(state);
+        self.shared.cvar.notify_one();
+
+        (self.shared.sink)(&out);
+    }
+
+    // Hands the reply to its pending RPC, or back if nobody waits for it
+    pub fn resolve(&self, reply: Message<Value>) -> Option<Message<Value>> {
+        let pending_opt = reply
+            .body
+            .in_reply_to
+            .and_then(|reply_to| self.lock().pending.remove(&reply_to));
+
+        match pending_opt {
+            Some(pending) => {
+                let result = match serde_json::from_value(reply.body.payload.clone()) {
+                    Ok(ErrorReplyPayload::Error(err)) => Err(err),
+                    Err(_) => Ok(reply),
+                };
+                (pending.callback)(result);
+                None
+            }
+            None => Some(reply),
+        }
+    }
+
+    pub fn stats(&self) -> RpcStats {
+        let state = self.lock();
+        RpcStats {
+            pending: state.pending.len(),
+            retries: state.retries,
+            abandoned: state.abandoned,
+        }
+    }
+}
+
+fn timer_loop(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.shutdown {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut resend = vec![];
+        let mut expired = vec![];
+        while let Some(&Reverse((deadline, msg_id))) = state.deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+            state.deadlines.pop();
+
+            let retry_deadline = match state.pending.get_mut(&msg_id) {
+                Some(p) if p.deadline == deadline && p.opts.can_retry(p.attempts) => {
+                    p.attempts += 1;
+                    p.deadline = now + p.opts.wait(p.attempts);
+                    resend.push(p.msg.clone());
+                    Some(p.deadline)
+                }
+                Some(p) if p.deadline == deadline => {
+                    expired.extend(state.pending.remove(&msg_id));
+                    None
+                }
+                // Already resolved, or rescheduled
+                _ => None,
+            };
+            if let Some(retry_deadline) = retry_deadline {
+                state.deadlines.push(Reverse((retry_deadline, msg_id)));
+            }
+        }
+        state.retries += resend.len();
+        state.abandoned += expired.len();
+
+        // Never write or call back while holding the lock
+        if !resend.is_empty() || !expired.is_empty() {
+            drop(state);
+            resend.iter().for_each(|msg| {
+                eprintln!("Retrying: {:?}", msg);
+                (shared.sink)(msg);
+            });
+            expired.into_iter().for_each(|p| {
+                let err = ErrorPayload::new(
+                    ErrorCode::Timeout,
+                    format!(
+                        "No response from {} to {:?} after {} attempts",
+                        p.msg.dest, p.msg.body.msg_id, p.attempts
+                    ),
+                );
+                (p.callback)(Err(err));
+            });
+            state = shared.state.lock().unwrap();
+            continue;
+        }
+
+        state = match state.deadlines.peek() {
+            Some(&Reverse((deadline, _))) => {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                shared.cvar.wait_timeout(state, wait).unwrap().0
+            }
+            None => shared.cvar.wait(state).unwrap(),
+        };
+    }
+}
@@ -0,0 +1,82 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::runtime::task::Task;
+
+// Named periodic tasks, each on its own thread. Started by the runtime from
+// `Handler::tasks`, they can also be started and stopped while running.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    running: Mutex<HashMap<String, Running>>,
+}
+
+#[derive(Debug)]
+struct Running {
+    // Dropping the sender stops the task, without waiting for its interval
+    _stop: Sender<()>,
+    millisec: u64,
+}
+
+pub(crate) fn wait(millisec: u64, jitter: f64) -> Duration {
+    let wait = Duration::from_millis(millisec);
+    if jitter > 0.0 {
+        wait + wait.mul_f64(rand::thread_rng().gen_range(0.0..=jitter))
+    } else {
+        wait
+    }
+}
+
+impl Scheduler {
+    // Runs the task on `node`, replacing any task of the same name
+    pub fn start<H>(&self, node: &Arc<H>, task: Task<H>)
+    where
+        H: Send + Sync + 'static,
+    {
+        let node = Arc::clone(node);
+        let callback = task.callback;
+        let (millisec, jitter) = (task.millisec, task.jitter);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let running = Running {
+            _stop: stop,
+            millisec,
+        };
+        self.running
+            .lock()
+            .unwrap()
+            .insert(task.name.clone(), running);
+
+        eprintln!("Starting task {} every {}ms", task.name, millisec);
+        thread::spawn(move || loop {
+            callback(&node);
+            match stopped.recv_timeout(wait(millisec, jitter)) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        });
+    }
+
+    // Returns whether the task was running. A run in progress completes.
+    pub fn stop(&self, name: &str) -> bool {
+        self.running.lock().unwrap().remove(name).is_some()
+    }
+
+    pub fn stop_all(&self) {
+        self.running.lock().unwrap().clear();
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running.lock().unwrap().contains_key(name)
+    }
+
+    // Interval the task runs at, environment overrides included
+    pub fn interval(&self, name: &str) -> Option<Duration> {
+        let running = self.running.lock().unwrap();
+        running
+            .get(name)
+            .map(|task| Duration::from_millis(task.millisec))
+    }
+}
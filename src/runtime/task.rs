@@ -0,0 +1,65 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::runtime::env_usize;
+
+pub type TaskFn<H> = Arc<dyn Fn(&H) + Send + Sync>;
+
+// Periodic job of a node, run by the scheduler every `millisec`, plus up to
+// `jitter` times that at random so that nodes do not fire in lockstep. The
+// async runtime runs its own callbacks, see `async_runtime::AsyncTask`.
+pub struct Task<H, F = TaskFn<H>> {
+    pub name: String,
+    pub callback: F,
+    pub millisec: u64,
+    pub jitter: f64,
+    node: PhantomData<fn(&H)>,
+}
+
+impl<H> Task<H> {
+    pub fn new<C>(name: &str, millisec: u64, callback: C) -> Self
+    where
+        C: Fn(&H) + Send + Sync + 'static,
+    {
+        Task::with_callback(name, millisec, Arc::new(callback))
+    }
+}
+
+impl<H, F> Task<H, F> {
+    pub fn with_callback(name: &str, millisec: u64, callback: F) -> Self {
+        Task {
+            name: name.to_owned(),
+            callback,
+            millisec,
+            jitter: 0.0,
+            node: PhantomData,
+        }
+    }
+
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        Task { jitter, ..self }
+    }
+
+    // <NAME>_INTERVAL (ms) overrides the interval, e.g. GOSSIP_INTERVAL
+    pub fn with_env(self) -> Self {
+        let key = format!("{}_INTERVAL", self.name.to_uppercase());
+        match env_usize(&key) {
+            Some(ms) => Task {
+                millisec: ms.max(1) as u64,
+                ..self
+            },
+            None => self,
+        }
+    }
+}
+
+impl<H, F> fmt::Debug for Task<H, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("name", &self.name)
+            .field("millisec", &self.millisec)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
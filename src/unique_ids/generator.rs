@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Node ids are unique within the cluster, so prefixing a local counter with
+// the node id gives globally unique ids without any coordination, even
+// across partitions.
+#[derive(Debug)]
+pub struct IdGenerator {
+    node_id: String,
+    counter: AtomicUsize,
+}
+
+impl IdGenerator {
+    pub fn new(node_id: String) -> Self {
+        IdGenerator {
+            node_id,
+            counter: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn next_id(&self) -> String {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{}", self.node_id, count)
+    }
+}
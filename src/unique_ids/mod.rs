@@ -0,0 +1,3 @@
+pub mod generator;
+pub mod msg;
+pub mod node;
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GenerateOkPayload {
+    pub id: String,
+}
+
+impl GenerateOkPayload {
+    pub fn new(id: String) -> Self {
+        GenerateOkPayload { id }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum ReqPayload {
+    #[serde(rename = "generate")]
+    Generate,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum SendPayload {
+    #[serde(rename = "generate_ok")]
+    GenerateOk(GenerateOkPayload),
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::protocol::{ErrorPayload, Message};
+use crate::runtime::context::Context;
+use crate::runtime::handler::Handler;
+use crate::unique_ids::generator::IdGenerator;
+use crate::unique_ids::msg::{GenerateOkPayload, ReqPayload, SendPayload};
+
+#[derive(Debug)]
+pub struct Node {
+    ctx: Arc<Context>,
+    generator: IdGenerator,
+}
+
+impl Handler for Node {
+    type Payload = ReqPayload;
+
+    fn init(ctx: Arc<Context>) -> Self {
+        ctx.log(&format!("Initiated node {:}", ctx.node_id));
+        let generator = IdGenerator::new(ctx.node_id.clone());
+        Node { ctx, generator }
+    }
+
+    fn handle(&self, request: Message<ReqPayload>) -> Result<(), ErrorPayload> {
+        let payload = match request.body.payload {
+            ReqPayload::Generate => {
+                SendPayload::GenerateOk(GenerateOkPayload::new(self.generator.next_id()))
+            }
+        };
+
+        self.ctx.reply(&request, payload);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "async")]
+
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+use echo_server::protocol::{Body, ErrorCode, Message};
+use echo_server::runtime::async_runtime::{spawn_task, AsyncTask};
+use echo_server::runtime::context::Context;
+use echo_server::runtime::rpc::RpcOptions;
+
+const PATIENCE: Duration = Duration::from_secs(5);
+
+// A single executor thread: whatever completes the RPCs, it is not one
+// thread per call
+fn runtime() -> Runtime {
+    Builder::new_current_thread().enable_all().build().unwrap()
+}
+
+// The node's RPC requests come out of the receiver
+fn context() -> (Arc<Context>, Receiver<Message<Value>>) {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let node_ids: HashSet<String> = ["n0", "n1"].iter().map(|&n| n.to_owned()).collect();
+    let ctx = Context::with_rpc_sink(
+        "n0".to_owned(),
+        node_ids,
+        Arc::new(move |msg| tx.lock().unwrap().send(msg.clone()).unwrap()),
+    );
+    (Arc::new(ctx), rx)
+}
+
+// Requests are only sent once their future is polled
+async fn sent(rx: &Receiver<Message<Value>>) -> Message<Value> {
+    for _ in 0..1000 {
+        if let Ok(msg) = rx.try_recv() {
+            return msg;
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    panic!("Nothing sent");
+}
+
+fn pong(request: &Message<Value>) -> Message<Value> {
+    let payload = json!({"type": "pong", "i": request.body.payload["i"]});
+    let body = Body::new(payload, Some(0), request.body.msg_id);
+    Message::new(request.dest.clone(), request.src.clone(), body)
+}
+
+fn ping(ctx: &Arc<Context>, i: usize, opts: RpcOptions) -> tokio::task::JoinHandle<Value> {
+    let ctx = Arc::clone(ctx);
+    tokio::spawn(async move {
+        match ctx
+            .async_rpc::<_, Value>("n1", json!({"type": "ping", "i": i}), opts)
+            .await
+        {
+            Ok(reply) => reply.body.payload,
+            Err(err) => json!({"type": "error", "code": usize::from(err.code)}),
+        }
+    })
+}
+
+#[test]
+fn rpc_future_resolves_on_its_reply() {
+    let (ctx, rx) = context();
+    runtime().block_on(async {
+        let call = ping(&ctx, 7, RpcOptions::new(PATIENCE));
+        let request = sent(&rx).await;
+        assert_eq!(request.dest, "n1");
+
+        assert!(ctx.resolve(pong(&request)).is_none());
+        let reply = call.await.unwrap();
+        assert_eq!(reply, json!({"type": "pong", "i": 7}));
+    });
+    assert_eq!(ctx.rpc_stats().pending, 0);
+}
+
+#[test]
+fn rpc_future_fails_on_timeout() {
+    let (ctx, rx) = context();
+    runtime().block_on(async {
+        let call = ping(&ctx, 0, RpcOptions::new(Duration::from_millis(20)));
+        sent(&rx).await;
+        let reply = call.await.unwrap();
+        assert_eq!(reply["code"], usize::from(ErrorCode::Timeout));
+    });
+    assert_eq!(ctx.rpc_stats().abandoned, 1);
+}
+
+#[test]
+fn thousands_of_rpcs_in_flight_on_one_thread() {
+    let (ctx, rx) = context();
+    let count = 2000;
+    runtime().block_on(async {
+        let calls: Vec<_> = (0..count)
+            .map(|i| ping(&ctx, i, RpcOptions::new(PATIENCE)))
+            .collect();
+        let mut requests = vec![];
+        for _ in 0..count {
+            requests.push(sent(&rx).await);
+        }
+        assert_eq!(ctx.rpc_stats().pending, count);
+
+        // Each future gets its own reply, whatever the order
+        requests.iter().rev().for_each(|request| {
+            assert!(ctx.resolve(pong(request)).is_none());
+        });
+        for (i, call) in calls.into_iter().enumerate() {
+            assert_eq!(call.await.unwrap()["i"], i);
+        }
+    });
+    assert_eq!(ctx.rpc_stats().pending, 0);
+}
+
+#[derive(Debug, Default)]
+struct Runs(AtomicUsize);
+
+fn counting(name: &str, millisec: u64) -> AsyncTask<Runs> {
+    AsyncTask::with_callback(name, millisec, |runs| {
+        Box::pin(async move {
+            runs.0.fetch_add(1, Ordering::SeqCst);
+        })
+    })
+}
+
+#[test]
+fn async_task_runs_on_the_executor_until_aborted() {
+    let runs = Arc::new(Runs::default());
+    runtime().block_on(async {
+        let task = spawn_task(&runs, counting("count", 5).with_jitter(0.2));
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(runs.0.load(Ordering::SeqCst) > 2);
+
+        task.abort();
+        let _ = task.await;
+        let stopped_at = runs.0.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(runs.0.load(Ordering::SeqCst), stopped_at);
+    });
+}
+
+#[test]
+fn async_task_interval_comes_from_the_environment() {
+    env::set_var("SLOW_PROBE_INTERVAL", "10000");
+    let runs = Arc::new(Runs::default());
+    runtime().block_on(async {
+        spawn_task(&runs, counting("slow_probe", 1));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    });
+    // The first run only, the next one is 10s away
+    assert_eq!(runs.0.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,251 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+use echo_server::crdt::causal::{Causal, CausalBuffer, CausalLog, CausalOp};
+use echo_server::crdt::crdt::CrdtTrait;
+use echo_server::crdt::msg::ElementPayload;
+use echo_server::crdt::op_orset::{ORSetEffect, OpORSet};
+use echo_server::crdt::orset::ORSetOp;
+use echo_server::crdt::vclock::VClock;
+
+const CASES: usize = 50;
+const NODES: [&str; 3] = ["n0", "n1", "n2"];
+
+fn clock(counters: &[(&str, u64)]) -> VClock {
+    VClock(
+        counters
+            .iter()
+            .map(|&(node, c)| (node.to_owned(), c))
+            .collect(),
+    )
+}
+
+fn op(origin: &str, counters: &[(&str, u64)]) -> CausalOp<usize> {
+    CausalOp {
+        origin: origin.to_owned(),
+        clock: clock(counters),
+        effect: counters.iter().map(|&(_, c)| c as usize).sum(),
+    }
+}
+
+// A node the way `crdt::node::Node` runs an operation-based CRDT
+struct Replica {
+    id: String,
+    crdt: Causal<OpORSet>,
+    log: CausalLog<Vec<ORSetEffect>>,
+}
+
+impl Replica {
+    fn new(id: &str) -> Self {
+        Replica {
+            id: id.to_owned(),
+            crdt: Causal::new(id, &HashSet::new()),
+            log: CausalLog::default(),
+        }
+    }
+
+    // Applied locally, then stamped as the replicate task does
+    fn apply(&mut self, op: ORSetOp) -> CausalOp<Vec<ORSetEffect>> {
+        self.crdt.apply(op).expect("OR-Set operation failed");
+        let delta = self.crdt.take_delta().expect("No effect to replicate");
+        self.log.stamp(&self.id, delta);
+        // The latest of what a peer never heard of
+        self.log.missing("c0", usize::MAX).pop().unwrap()
+    }
+
+    fn add(&mut self, element: usize) -> CausalOp<Vec<ORSetEffect>> {
+        self.apply(ORSetOp::Add(ElementPayload::new(element)))
+    }
+
+    fn remove(&mut self, element: usize) -> CausalOp<Vec<ORSetEffect>> {
+        self.apply(ORSetOp::Remove(ElementPayload::new(element)))
+    }
+
+    fn receive(&mut self, op: CausalOp<Vec<ORSetEffect>>) {
+        self.log
+            .receive(op)
+            .into_iter()
+            .for_each(|effects| self.crdt.merge(effects));
+    }
+
+    fn read(&self) -> HashSet<usize> {
+        self.crdt.read()
+    }
+}
+
+#[test]
+fn deliverable_only_right_after_what_was_delivered() {
+    let delivered = clock(&[("n0", 2), ("n1", 1)]);
+    // (origin, clock, deliverable)
+    let cases = [
+        ("n0", &[("n0", 3)][..], true),
+        ("n0", &[("n0", 3), ("n1", 1)][..], true),
+        ("n2", &[("n2", 1), ("n0", 2)][..], true),
+        // Already delivered
+        ("n0", &[("n0", 2)][..], false),
+        // A gap from its origin
+        ("n0", &[("n0", 4)][..], false),
+        // Depends on an operation not delivered yet
+        ("n0", &[("n0", 3), ("n1", 2)][..], false),
+        ("n2", &[("n2", 1), ("n1", 5)][..], false),
+    ];
+    for (origin, counters, deliverable) in cases {
+        assert_eq!(
+            clock(counters).deliverable(origin, &delivered),
+            deliverable,
+            "{} {:?}",
+            origin,
+            counters
+        );
+    }
+}
+
+#[test]
+fn buffer_holds_back_operations_until_their_dependencies_arrive() {
+    let mut buffer = CausalBuffer::default();
+    let first = op("n0", &[("n0", 1)]);
+    let second = op("n0", &[("n0", 2)]);
+    // Sent by n1 once it had delivered both
+    let third = op("n1", &[("n0", 2), ("n1", 1)]);
+
+    assert!(buffer.receive(third.clone()).is_empty());
+    assert!(buffer.receive(second.clone()).is_empty());
+    assert_eq!(buffer.pending(), 2);
+
+    let ready = buffer.receive(first.clone());
+    assert_eq!(ready, [first.clone(), second, third.clone()]);
+    assert_eq!(buffer.pending(), 0);
+    assert_eq!(buffer.delivered(), &clock(&[("n0", 2), ("n1", 1)]));
+
+    // Delivered exactly once
+    assert!(buffer.receive(first).is_empty());
+    assert!(buffer.receive(third).is_empty());
+    let fourth = op("n1", &[("n0", 2), ("n1", 2)]);
+    assert_eq!(buffer.receive(fourth.clone()).len(), 1);
+    assert!(buffer.receive(fourth).is_empty());
+}
+
+#[test]
+fn remove_never_overtakes_the_add_it_observed() {
+    let mut n0 = Replica::new("n0");
+    let mut n1 = Replica::new("n1");
+    let mut n2 = Replica::new("n2");
+
+    let add = n0.add(1);
+    n1.receive(add.clone());
+    let remove = n1.remove(1);
+    assert!(n1.read().is_empty());
+
+    // Out of order on n2: the remove waits for the add
+    n2.receive(remove.clone());
+    assert_eq!(n2.log.pending(), 1);
+    n2.receive(add);
+    n0.receive(remove);
+    for replica in [&n0, &n1, &n2] {
+        assert!(replica.read().is_empty(), "{}", replica.id);
+        assert_eq!(replica.log.pending(), 0, "{}", replica.id);
+    }
+}
+
+#[test]
+fn replicas_converge_whatever_the_delivery_order() {
+    let mut rng = StdRng::seed_from_u64(20);
+    for case in 0..CASES {
+        let mut replicas: Vec<Replica> = NODES.iter().map(|node| Replica::new(node)).collect();
+        // Operations on their way, by destination
+        let mut network: Vec<(usize, CausalOp<Vec<ORSetEffect>>)> = vec![];
+
+        for _ in 0..rng.gen_range(1..40) {
+            let i = rng.gen_range(0..NODES.len());
+            let element = rng.gen_range(0..5);
+            let op = if rng.gen_bool(0.6) {
+                replicas[i].add(element)
+            } else if replicas[i].read().contains(&element) {
+                replicas[i].remove(element)
+            } else {
+                continue;
+            };
+            (0..NODES.len())
+                .filter(|&dest| dest != i)
+                .for_each(|dest| network.push((dest, op.clone())));
+
+            // Some arrive now, in any order, some twice
+            network.shuffle(&mut rng);
+            let arrived = rng.gen_range(0..=network.len());
+            for (dest, op) in network.split_off(network.len() - arrived) {
+                if rng.gen_bool(0.2) {
+                    network.push((dest, op.clone()));
+                }
+                replicas[dest].receive(op);
+            }
+        }
+        network.shuffle(&mut rng);
+        for (dest, op) in network {
+            replicas[dest].receive(op);
+        }
+
+        let expected = replicas[0].read();
+        for replica in &replicas {
+            assert_eq!(replica.read(), expected, "case {}, {}", case, replica.id);
+            assert_eq!(replica.log.pending(), 0, "case {}, {}", case, replica.id);
+        }
+    }
+}
+
+#[test]
+fn operations_are_relayed_from_the_log_until_acked() {
+    let mut n0 = Replica::new("n0");
+    let mut n1 = Replica::new("n1");
+    let mut n2 = Replica::new("n2");
+    (0..3).for_each(|element| {
+        n0.add(element);
+    });
+
+    // n2 only hears from n1, that forwards what it got from n0
+    n0.log
+        .missing("n1", usize::MAX)
+        .into_iter()
+        .for_each(|op| n1.receive(op));
+    let relayed = n1.log.missing("n2", 2);
+    assert_eq!(relayed.len(), 2);
+    relayed.into_iter().for_each(|op| n2.receive(op));
+    n1.log.ack("n2", n2.log.delivered());
+
+    let rest = n1.log.missing("n2", usize::MAX);
+    assert_eq!(rest.len(), 1);
+    rest.into_iter().for_each(|op| n2.receive(op));
+    n1.log.ack("n2", n2.log.delivered());
+    assert!(n1.log.missing("n2", usize::MAX).is_empty());
+    assert_eq!(n2.read(), (0..3).collect());
+}
+
+#[test]
+fn history_is_pruned_once_every_peer_delivered_it() {
+    let mut n0 = Replica::new("n0");
+    let mut n1 = Replica::new("n1");
+    let mut n2 = Replica::new("n2");
+    let ops: Vec<_> = (0..3).map(|element| n0.add(element)).collect();
+    let peers: HashSet<String> = ["n1", "n2"].iter().map(|&p| p.to_owned()).collect();
+
+    ops.iter().for_each(|op| n1.receive(op.clone()));
+    n0.log.ack("n1", n1.log.delivered());
+    // Nothing heard from n2 yet
+    n0.log.prune(&peers);
+    assert_eq!(n0.log.len(), 3);
+
+    n2.receive(ops[0].clone());
+    n0.log.ack("n2", n2.log.delivered());
+    n0.log.prune(&peers);
+    assert_eq!(n0.log.len(), 2);
+    assert_eq!(n0.log.missing("n2", usize::MAX), ops[1..]);
+
+    // Acks arriving out of order never move backwards
+    ops[1..].iter().for_each(|op| n2.receive(op.clone()));
+    n0.log.ack("n2", n2.log.delivered());
+    n0.log.ack("n2", &clock(&[("n0", 1)]));
+    n0.log.prune(&peers);
+    assert!(n0.log.is_empty());
+    assert_eq!(n2.read(), n1.read());
+}
@@ -0,0 +1,37 @@
+// Fixtures shared by the Raft tests, not every test crate uses them all
+#![allow(dead_code)]
+
+use std::collections::HashSet;
+
+use echo_server::raft::log::LogEntry;
+use echo_server::raft::msg::{OpPayload, WritePayload};
+use echo_server::raft::state::RaftState;
+use echo_server::raft::storage::MemoryStorage;
+
+pub const CLUSTER: [&str; 3] = ["n0", "n1", "n2"];
+
+// A fresh follower, its state kept in memory
+pub fn node(id: &str, cluster: &[&str]) -> RaftState {
+    let peers: HashSet<String> = cluster
+        .iter()
+        .filter(|&&peer| peer != id)
+        .map(|&peer| peer.to_owned())
+        .collect();
+    RaftState::new(id.to_owned(), peers, Box::new(MemoryStorage))
+}
+
+// Elected in a first term with the vote of `voter`
+pub fn leader(id: &str, cluster: &[&str], voter: &str) -> RaftState {
+    let mut state = node(id, cluster);
+    let req = state.become_candidate();
+    assert!(state.record_vote(req.term, voter.to_owned(), true));
+    state
+}
+
+// Entries are told apart by their value, along with their term
+pub fn entry(term: u64, value: usize) -> LogEntry {
+    LogEntry {
+        term,
+        op: OpPayload::Write(WritePayload::new(0, value)),
+    }
+}
@@ -0,0 +1,154 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+use echo_server::crdt::crdt::CrdtTrait;
+use echo_server::crdt::hlc::{HybridClock, Timestamp};
+use echo_server::crdt::lww::{
+    LwwMap, LwwMapData, LwwRegister, LwwRegisterOp, Versioned, WritePayload,
+};
+
+const CASES: usize = 200;
+const NODES: [&str; 4] = ["n0", "n1", "n2", "n3"];
+
+fn timestamp(physical: u64, logical: u64, node: &str) -> Timestamp {
+    Timestamp {
+        physical,
+        logical,
+        node: node.to_owned(),
+    }
+}
+
+// A node writes once per timestamp, so the value is told by it
+fn write_at(timestamp: Timestamp) -> Versioned {
+    let value = json!(format!(
+        "{}.{}.{}",
+        timestamp.physical, timestamp.logical, timestamp.node
+    ));
+    Versioned::new(value, timestamp)
+}
+
+fn empty() -> LwwMap {
+    LwwMap::new("c0", &HashSet::new())
+}
+
+// Random replica state, from writes to a few keys at close timestamps, so
+// that many collide on all but the node
+fn arbitrary(rng: &mut StdRng) -> LwwMap {
+    let map = empty();
+    for _ in 0..rng.gen_range(0..20) {
+        let node = NODES[rng.gen_range(0..NODES.len())];
+        let versioned = write_at(timestamp(rng.gen_range(0..3), rng.gen_range(0..3), node));
+        let write = HashMap::from([(rng.gen_range(0..5), versioned)]);
+        map.merge(LwwMapData(write));
+    }
+    map
+}
+
+fn join(a: &LwwMap, b: &LwwMap) -> LwwMap {
+    let joined = empty();
+    joined.merge(a.data());
+    joined.merge(b.data());
+    joined
+}
+
+#[test]
+fn clock_stays_ahead_of_a_peer_clock_it_observed() {
+    let clock = HybridClock::default();
+    let local = clock.now("n0");
+    // An hour ahead of the local wall clock
+    let ahead = timestamp(local.physical + 3_600_000, 7, "n1");
+    clock.observe(&ahead);
+
+    let mut last = clock.now("n0");
+    assert!(last > ahead, "{:?} after {:?}", last, ahead);
+    assert_eq!(last.physical, ahead.physical);
+    for _ in 0..1000 {
+        let next = clock.now("n0");
+        assert!(next > last, "{:?} after {:?}", next, last);
+        last = next;
+    }
+
+    // Observing the past changes nothing
+    clock.observe(&local);
+    assert!(clock.now("n0") > last);
+}
+
+#[test]
+fn concurrent_writes_resolve_the_same_on_every_replica() {
+    let replicas: Vec<LwwRegister> = NODES
+        .iter()
+        .map(|node| LwwRegister::new(node, &HashSet::new()))
+        .collect();
+    // Same time on every node, only the node tells them apart
+    let writes: Vec<Versioned> = NODES
+        .iter()
+        .map(|node| write_at(timestamp(100, 2, node)))
+        .collect();
+
+    for (i, replica) in replicas.iter().enumerate() {
+        let mut order: Vec<&Versioned> = writes.iter().collect();
+        order.rotate_left(i);
+        if i % 2 == 1 {
+            order.reverse();
+        }
+        order
+            .into_iter()
+            .for_each(|w| replica.merge(Some(w.clone())));
+    }
+    replicas.iter().for_each(|r| {
+        assert_eq!(r.data().as_ref(), writes.last());
+        assert_eq!(r.read(), json!("100.2.n3"));
+    });
+}
+
+#[test]
+fn local_write_after_a_merge_wins() {
+    let register = LwwRegister::new("n0", &HashSet::new());
+    let ahead = timestamp(u64::MAX / 2, 0, "n1");
+    register.merge(Some(write_at(ahead)));
+
+    // The local clock lags behind n1's, the write is still the latest
+    let write = WritePayload { value: json!(1) };
+    register.apply(LwwRegisterOp::Write(write)).unwrap();
+    assert_eq!(register.read(), json!(1));
+}
+
+#[test]
+fn merge_is_commutative() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..CASES {
+        let (a, b) = (arbitrary(&mut rng), arbitrary(&mut rng));
+        assert_eq!(join(&a, &b).data(), join(&b, &a).data());
+    }
+}
+
+#[test]
+fn merge_is_associative() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..CASES {
+        let (a, b, c) = (
+            arbitrary(&mut rng),
+            arbitrary(&mut rng),
+            arbitrary(&mut rng),
+        );
+        assert_eq!(
+            join(&join(&a, &b), &c).data(),
+            join(&a, &join(&b, &c)).data()
+        );
+    }
+}
+
+#[test]
+fn merge_is_idempotent() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..CASES {
+        let a = arbitrary(&mut rng);
+        assert_eq!(join(&a, &a).data(), a.data());
+
+        let before = a.data();
+        a.merge(a.data());
+        assert_eq!(a.data(), before);
+    }
+}
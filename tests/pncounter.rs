@@ -0,0 +1,131 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+use echo_server::crdt::crdt::CrdtTrait;
+use echo_server::crdt::gcounter::CounterOp;
+use echo_server::crdt::msg::DeltaPayload;
+use echo_server::crdt::pncounter::PNCounter;
+
+const CASES: usize = 200;
+const NODES: [&str; 4] = ["n0", "n1", "n2", "n3"];
+
+fn replica(node: &str) -> PNCounter {
+    PNCounter::new(node, &HashSet::new())
+}
+
+fn empty() -> PNCounter {
+    replica("c0")
+}
+
+fn add(counter: &PNCounter, delta: i64) {
+    counter
+        .apply(CounterOp::Add(DeltaPayload::new(delta)))
+        .expect("PN-Counter add failed");
+}
+
+// Random replica state, built from increments and decrements on random nodes
+fn arbitrary(rng: &mut StdRng) -> PNCounter {
+    let replicas: Vec<PNCounter> = NODES.iter().map(|node| replica(node)).collect();
+    for _ in 0..rng.gen_range(0..20) {
+        add(
+            &replicas[rng.gen_range(0..NODES.len())],
+            rng.gen_range(-50..=50),
+        );
+    }
+    let counter = empty();
+    replicas.iter().for_each(|r| counter.merge(r.data()));
+    counter
+}
+
+fn join(a: &PNCounter, b: &PNCounter) -> PNCounter {
+    let joined = empty();
+    joined.merge(a.data());
+    joined.merge(b.data());
+    joined
+}
+
+#[test]
+fn merge_is_commutative() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..CASES {
+        let (a, b) = (arbitrary(&mut rng), arbitrary(&mut rng));
+        assert_eq!(join(&a, &b).data(), join(&b, &a).data());
+    }
+}
+
+#[test]
+fn merge_is_associative() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..CASES {
+        let (a, b, c) = (
+            arbitrary(&mut rng),
+            arbitrary(&mut rng),
+            arbitrary(&mut rng),
+        );
+        assert_eq!(
+            join(&join(&a, &b), &c).data(),
+            join(&a, &join(&b, &c)).data()
+        );
+    }
+}
+
+#[test]
+fn merge_is_idempotent() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..CASES {
+        let a = arbitrary(&mut rng);
+        assert_eq!(join(&a, &a).data(), a.data());
+
+        let before = a.data();
+        a.merge(a.data());
+        assert_eq!(a.data(), before);
+    }
+}
+
+#[test]
+fn stale_states_do_not_roll_back() {
+    let mut rng = StdRng::seed_from_u64(4);
+    for _ in 0..CASES {
+        let (a, b) = (arbitrary(&mut rng), arbitrary(&mut rng));
+        let joined = join(&a, &b);
+        let expected = joined.data();
+
+        // Late, duplicated deliveries of the states already merged in
+        joined.merge(a.data());
+        joined.merge(b.data());
+        joined.merge(empty().data());
+        assert_eq!(joined.data(), expected);
+    }
+}
+
+#[test]
+fn replicas_converge_whatever_the_delivery_order() {
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..CASES {
+        // Each node only counts its own deltas
+        let replicas: Vec<PNCounter> = NODES.iter().map(|node| replica(node)).collect();
+        let mut expected = 0;
+        for _ in 0..rng.gen_range(1..40) {
+            let i = rng.gen_range(0..NODES.len());
+            let delta = rng.gen_range(-50..=50);
+            add(&replicas[i], delta);
+            expected += delta;
+        }
+
+        // Exchange states in random order, with duplicates, until everybody
+        // has heard from everybody
+        let states: Vec<_> = replicas.iter().map(|r| r.data()).collect();
+        for (i, replica) in replicas.iter().enumerate() {
+            let mut order: Vec<usize> = (0..NODES.len()).filter(|&j| j != i).collect();
+            order.extend((0..3).map(|_| rng.gen_range(0..NODES.len())));
+            order.sort_by_key(|_| rng.gen::<u32>());
+            order.iter().for_each(|&j| replica.merge(states[j].clone()));
+        }
+
+        replicas.iter().for_each(|r| {
+            assert_eq!(r.value(), expected);
+            assert_eq!(r.data(), replicas[0].data());
+        });
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use echo_server::protocol::{ErrorCode, Message};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Payload {
+    #[serde(rename = "echo")]
+    Echo { echo: String },
+}
+
+fn typed(payload: Value) -> Result<Message<Payload>, ErrorCode> {
+    let mut body = payload;
+    body["msg_id"] = json!(1);
+    let raw: Message<Value> =
+        serde_json::from_value(json!({"src": "c1", "dest": "n0", "body": body})).unwrap();
+    raw.into_typed().map_err(|err| err.code)
+}
+
+#[test]
+fn unknown_types_are_not_supported_bad_fields_malformed() {
+    let cases = [
+        (json!({"type": "bogus"}), Err(ErrorCode::NotSupported)),
+        (
+            json!({"type": "bogus", "echo": "hi"}),
+            Err(ErrorCode::NotSupported),
+        ),
+        (json!({"type": "echo"}), Err(ErrorCode::MalformedRequest)),
+        (
+            json!({"type": "echo", "echo": 3}),
+            Err(ErrorCode::MalformedRequest),
+        ),
+        (json!({"echo": "hi"}), Err(ErrorCode::MalformedRequest)),
+        (json!({"type": "echo", "echo": "hi"}), Ok(())),
+    ];
+    for (payload, expected) in cases {
+        let result = typed(payload.clone()).map(|msg| match msg.body.payload {
+            Payload::Echo { echo } => assert_eq!(echo, "hi"),
+        });
+        assert_eq!(result, expected, "{}", payload);
+    }
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use echo_server::raft::msg::{AppendEntriesPayload, ReqPayload, RequestVotePayload};
+use echo_server::raft::state::Role;
+
+use common::{entry, leader, node, CLUSTER};
+
+fn vote_request(candidate: &str, term: u64, last_log: (u64, u64)) -> RequestVotePayload {
+    let (last_log_term, last_log_index) = last_log;
+    RequestVotePayload {
+        term,
+        candidate_id: candidate.to_owned(),
+        last_log_index,
+        last_log_term,
+        pre_vote: false,
+    }
+}
+
+fn pre_vote_request(candidate: &str, term: u64, last_log: (u64, u64)) -> RequestVotePayload {
+    RequestVotePayload {
+        pre_vote: true,
+        ..vote_request(candidate, term, last_log)
+    }
+}
+
+// A leader's append_entries carrying entries of the given terms
+fn append(leader: &str, term: u64, entry_terms: &[u64]) -> AppendEntriesPayload {
+    let entries = entry_terms
+        .iter()
+        .enumerate()
+        .map(|(i, &term)| entry(term, i))
+        .collect();
+    AppendEntriesPayload {
+        term,
+        leader_id: leader.to_owned(),
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries,
+        leader_commit: 0,
+    }
+}
+
+#[test]
+fn one_vote_per_term() {
+    let mut state = node("n0", &CLUSTER);
+
+    let reply = state.request_vote(&vote_request("n1", 1, (0, 0)));
+    assert!(reply.vote_granted);
+    assert_eq!((reply.term, state.current_term), (1, 1));
+    assert_eq!(state.voted_for.as_deref(), Some("n1"));
+
+    assert!(
+        !state
+            .request_vote(&vote_request("n2", 1, (0, 0)))
+            .vote_granted
+    );
+    // The same candidate asking again, its first request lost
+    assert!(
+        state
+            .request_vote(&vote_request("n1", 1, (0, 0)))
+            .vote_granted
+    );
+    // A new term, a new vote
+    assert!(
+        state
+            .request_vote(&vote_request("n2", 2, (0, 0)))
+            .vote_granted
+    );
+    assert_eq!(state.voted_for.as_deref(), Some("n2"));
+}
+
+#[test]
+fn stale_terms_are_refused() {
+    let mut state = node("n0", &CLUSTER);
+    state.observe_term(3);
+
+    let reply = state.request_vote(&vote_request("n1", 2, (0, 0)));
+    assert!(!reply.vote_granted);
+    assert_eq!(reply.term, 3);
+
+    let reply = state.append_entries(append("n1", 2, &[2]));
+    assert!(!reply.success);
+    assert_eq!(reply.term, 3);
+    assert_eq!(state.log.last_index(), 0);
+}
+
+#[test]
+fn votes_only_for_logs_at_least_as_up_to_date() {
+    // (last log term, last log index) of the candidate, against a voter
+    // holding entries of terms 1, 1, 2
+    let cases = [
+        ((2, 3), true),
+        ((2, 4), true),
+        ((3, 1), true),
+        ((2, 2), false),
+        ((1, 3), false),
+        ((1, 10), false),
+        ((0, 0), false),
+    ];
+    for (last_log, granted) in cases {
+        let mut state = node("n0", &CLUSTER);
+        assert!(state.append_entries(append("n2", 2, &[1, 1, 2])).success);
+
+        let reply = state.request_vote(&vote_request("n1", 3, last_log));
+        assert_eq!(reply.vote_granted, granted, "candidate log {:?}", last_log);
+        // The term moves on either way
+        assert_eq!(state.current_term, 3);
+    }
+}
+
+#[test]
+fn candidate_needs_a_majority() {
+    for (cluster, votes_needed) in [(&CLUSTER[..], 1), (&["n0", "n1", "n2", "n3", "n4"][..], 2)] {
+        let mut state = node("n0", cluster);
+        let req = state.become_candidate();
+        assert_eq!((state.role, state.current_term), (Role::Candidate, 1));
+        assert_eq!(state.voted_for.as_deref(), Some("n0"));
+
+        // Refused, repeated and stale votes count for nothing
+        assert!(!state.record_vote(req.term, "n1".to_owned(), false));
+        assert!(!state.record_vote(req.term - 1, "n2".to_owned(), true));
+        for (i, voter) in cluster[1..=votes_needed].iter().enumerate() {
+            let won = state.record_vote(req.term, voter.to_string(), true);
+            assert_eq!(won, i + 1 == votes_needed, "{} votes", i + 1);
+            if !won {
+                assert!(!state.record_vote(req.term, voter.to_string(), true));
+            }
+        }
+        assert!(state.is_leader());
+        assert_eq!(state.leader.as_deref(), Some("n0"));
+    }
+}
+
+#[test]
+fn single_node_elects_itself() {
+    let mut state = node("n0", &["n0"]);
+    state.become_pre_candidate();
+    assert!(state.try_become_candidate().is_some());
+    assert!(state.try_become_leader());
+    assert_eq!(state.current_term, 1);
+}
+
+#[test]
+fn later_term_turns_anyone_into_a_follower() {
+    let mut state = leader("n0", &CLUSTER, "n1");
+    assert!(state.observe_term(4));
+    assert_eq!((state.role, state.current_term), (Role::Follower, 4));
+    assert_eq!(
+        (state.voted_for.as_ref(), state.leader.as_ref()),
+        (None, None)
+    );
+    assert!(!state.observe_term(4));
+
+    let mut state = node("n0", &CLUSTER);
+    state.become_candidate();
+    assert!(
+        state
+            .request_vote(&vote_request("n1", 2, (0, 0)))
+            .vote_granted
+    );
+    assert_eq!(state.role, Role::Follower);
+
+    // A candidate hearing from the leader of its own term lost
+    let mut state = node("n0", &CLUSTER);
+    state.become_candidate();
+    assert!(state.append_entries(append("n1", 1, &[])).success);
+    assert_eq!((state.role, state.current_term), (Role::Follower, 1));
+    assert_eq!(state.leader.as_deref(), Some("n1"));
+}
+
+#[test]
+fn pre_vote_changes_no_term_nor_vote() {
+    let mut candidate = node("n0", &CLUSTER);
+    let req = candidate.become_pre_candidate();
+    assert!(req.pre_vote);
+    assert_eq!((req.term, candidate.current_term), (1, 0));
+    assert_eq!(candidate.role, Role::PreCandidate);
+    assert_eq!(candidate.voted_for, None);
+
+    let mut voter = node("n1", &CLUSTER);
+    let reply = voter.request_vote(&req);
+    assert!(reply.vote_granted);
+    assert_eq!((reply.term, voter.current_term), (0, 0));
+    assert_eq!(voter.voted_for, None);
+
+    // Then the real election, in the next term
+    let vote = candidate
+        .record_pre_vote(req.term, "n1".to_owned(), reply.vote_granted)
+        .expect("A majority granted the pre-vote");
+    assert!(!vote.pre_vote);
+    assert_eq!((vote.term, candidate.current_term), (1, 1));
+    assert_eq!(candidate.role, Role::Candidate);
+}
+
+#[test]
+fn pre_vote_refused_while_a_leader_is_heard_from() {
+    let cluster = CLUSTER;
+    let mut follower = node("n1", &cluster);
+    assert!(follower.append_entries(append("n0", 1, &[1])).success);
+
+    let req = pre_vote_request("n2", 2, (1, 1));
+    assert!(!follower.request_vote(&req).vote_granted);
+    let mut leader = leader("n0", &cluster, "n1");
+    assert!(!leader.request_vote(&req).vote_granted);
+
+    // Nor for a term not past ours
+    let mut fresh = node("n1", &cluster);
+    fresh.observe_term(2);
+    assert!(
+        !fresh
+            .request_vote(&pre_vote_request("n2", 2, (0, 0)))
+            .vote_granted
+    );
+    assert!(
+        fresh
+            .request_vote(&pre_vote_request("n2", 3, (0, 0)))
+            .vote_granted
+    );
+}
+
+#[test]
+fn isolated_node_cannot_depose_the_leader() {
+    let cluster = CLUSTER;
+    let mut leader = leader("n0", &cluster, "n1");
+    let mut follower = node("n1", &cluster);
+    let mut isolated = node("n2", &cluster);
+    let term = leader.current_term;
+
+    let mut heartbeats = leader.append_targets();
+    heartbeats.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut heartbeats = heartbeats.into_iter().map(|(_, req)| match req {
+        ReqPayload::AppendEntries(req) => req,
+        other => panic!("Not an append_entries: {:?}", other),
+    });
+    let (to_follower, to_isolated) = (heartbeats.next().unwrap(), heartbeats.next().unwrap());
+    let reply = follower.append_entries(to_follower);
+    leader.append_reply("n1", Some(reply));
+
+    // Partitioned away, its elections never get past the pre-vote
+    for _ in 0..10 {
+        let req = isolated.become_pre_candidate();
+        assert!(!follower.request_vote(&req).vote_granted);
+        assert!(isolated
+            .record_pre_vote(req.term, "n1".to_owned(), false)
+            .is_none());
+    }
+    assert_eq!(isolated.current_term, 0);
+
+    // Back in the cluster, the heartbeat it missed gets through: it follows
+    // the leader, that stays in place
+    let reply = isolated.append_entries(to_isolated);
+    assert!(reply.success);
+    leader.append_reply("n2", Some(reply));
+    assert!(leader.is_leader());
+    assert_eq!(leader.current_term, term);
+    assert_eq!(
+        (isolated.role, isolated.current_term),
+        (Role::Follower, term)
+    );
+}
@@ -0,0 +1,331 @@
+mod common;
+
+use echo_server::raft::log::Log;
+use echo_server::raft::msg::{
+    AppendEntriesOkPayload, AppendEntriesPayload, OpPayload, ReqPayload, WritePayload,
+};
+use echo_server::raft::state::RaftState;
+
+use common::{entry, leader, node, CLUSTER};
+
+fn log_of(terms: &[u64]) -> Log {
+    let mut log = Log::default();
+    terms.iter().for_each(|&term| {
+        log.append(entry(term, 0));
+    });
+    log
+}
+
+fn terms(log: &Log) -> Vec<u64> {
+    (log.base_index() + 1..=log.last_index())
+        .map(|i| log.term_at(i).unwrap())
+        .collect()
+}
+
+// Elected in term 2, holding `count` entries of a term 1 leader gone before
+// committing them
+fn elected_with_log(count: usize) -> RaftState {
+    let mut state = node("n0", &CLUSTER);
+    let reply = state.append_entries(AppendEntriesPayload {
+        term: 1,
+        leader_id: "n2".to_owned(),
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: (0..count).map(|i| entry(1, i)).collect(),
+        leader_commit: 0,
+    });
+    assert!(reply.success);
+
+    let req = state.become_candidate();
+    assert!(state.record_vote(req.term, "n1".to_owned(), true));
+    state
+}
+
+fn write(state: &mut RaftState, value: usize) -> u64 {
+    state
+        .propose(OpPayload::Write(WritePayload::new(0, value)))
+        .expect("Not the leader")
+}
+
+fn append_for(leader: &mut RaftState, peer: &str) -> AppendEntriesPayload {
+    leader
+        .append_targets()
+        .into_iter()
+        .find_map(|(dest, req)| match req {
+            ReqPayload::AppendEntries(req) if dest == peer => Some(req),
+            _ => None,
+        })
+        .unwrap_or_else(|| panic!("No append_entries for {}", peer))
+}
+
+fn ack(leader: &mut RaftState, peer: &str, match_index: u64) {
+    let reply = AppendEntriesOkPayload {
+        term: leader.current_term,
+        success: true,
+        match_index,
+    };
+    leader.append_reply(peer, Some(reply));
+}
+
+// Log terms, prev index, new entry terms, resulting terms, indices written
+type MergeCase = (
+    &'static [u64],
+    u64,
+    &'static [u64],
+    &'static [u64],
+    &'static [u64],
+);
+
+#[test]
+fn merge_keeps_matching_entries_and_replaces_conflicting_ones() {
+    let cases: [MergeCase; 8] = [
+        (&[], 0, &[1, 1], &[1, 1], &[1, 2]),
+        (&[1, 1], 2, &[], &[1, 1], &[]),
+        (&[1, 1], 2, &[1, 2], &[1, 1, 1, 2], &[3, 4]),
+        // A late, shorter append drops nothing
+        (&[1, 1, 1], 1, &[1], &[1, 1, 1], &[]),
+        (&[1, 1, 1], 0, &[1, 1, 1, 2], &[1, 1, 1, 2], &[4]),
+        // Everything from the first conflict on goes
+        (&[1, 1, 1], 1, &[2], &[1, 2], &[2]),
+        (&[1, 1, 2, 2], 2, &[2, 3], &[1, 1, 2, 3], &[4]),
+        (&[1, 1, 2, 2], 1, &[3, 3], &[1, 3, 3], &[2, 3]),
+    ];
+    for (before, prev_index, new, after, written) in cases {
+        let mut log = log_of(before);
+        let new_entries = new.iter().map(|&term| entry(term, 1)).collect();
+        let merged = log.merge(prev_index, new_entries);
+
+        let label = (before, prev_index, new);
+        assert_eq!(terms(&log), after, "{:?}", label);
+        let indices: Vec<u64> = merged.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, written, "{:?}", label);
+        // Entries written are the new ones, of value 1, the others kept
+        for index in 1..=log.last_index() {
+            let value = if written.contains(&index) { 1 } else { 0 };
+            let expected = entry(log.term_at(index).unwrap(), value);
+            assert_eq!(log.get(index), Some(&expected), "{:?}", label);
+        }
+    }
+}
+
+#[test]
+fn merge_skips_compacted_entries() {
+    let mut log = log_of(&[1, 1, 1, 1]);
+    log.compact(2);
+
+    let merged = log.merge(1, vec![entry(1, 1), entry(1, 1), entry(2, 1)]);
+    assert_eq!(log.base_index(), 2);
+    assert_eq!(terms(&log), [1, 2]);
+    assert_eq!(merged.len(), 1);
+    assert_eq!(merged[0].0, 4);
+}
+
+#[test]
+fn append_entries_checks_the_previous_entry() {
+    // (prev index, prev term, success, match index or retry hint)
+    let cases = [
+        (3, 2, true, 4),
+        (2, 1, true, 3),
+        (0, 0, true, 1),
+        // Conflicting term: retry from before it
+        (3, 1, false, 2),
+        (2, 2, false, 1),
+        // Missing entries: retry past our log
+        (5, 2, false, 3),
+    ];
+    for (prev_log_index, prev_log_term, success, match_index) in cases {
+        let mut follower = node("n1", &CLUSTER);
+        let mut req = AppendEntriesPayload {
+            term: 2,
+            leader_id: "n0".to_owned(),
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![entry(1, 0), entry(1, 0), entry(2, 0)],
+            leader_commit: 0,
+        };
+        assert!(follower.append_entries(req.clone()).success);
+
+        req.prev_log_index = prev_log_index;
+        req.prev_log_term = prev_log_term;
+        req.entries = vec![entry(2, 1)];
+        let reply = follower.append_entries(req);
+        let label = (prev_log_index, prev_log_term);
+        assert_eq!(reply.success, success, "{:?}", label);
+        assert_eq!(reply.match_index, match_index, "{:?}", label);
+        if !success {
+            assert_eq!(terms(&follower.log), [1, 1, 2], "{:?}", label);
+        }
+    }
+}
+
+#[test]
+fn follower_commits_up_to_what_it_holds() {
+    // (leader commit, entries sent, commit index)
+    let cases = [(0, 2, 0), (1, 2, 1), (2, 2, 2), (5, 2, 2), (5, 0, 0)];
+    for (leader_commit, sent, commit_index) in cases {
+        let mut follower = node("n1", &CLUSTER);
+        let reply = follower.append_entries(AppendEntriesPayload {
+            term: 1,
+            leader_id: "n0".to_owned(),
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: (0..sent).map(|i| entry(1, i)).collect(),
+            leader_commit,
+        });
+        assert!(reply.success);
+        assert_eq!(
+            follower.commit_index,
+            commit_index,
+            "{:?}",
+            (leader_commit, sent)
+        );
+    }
+}
+
+#[test]
+fn stale_append_never_moves_the_commit_index_back() {
+    let mut follower = node("n1", &CLUSTER);
+    let first = AppendEntriesPayload {
+        term: 1,
+        leader_id: "n0".to_owned(),
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: (1..=128).map(|i| entry(1, i)).collect(),
+        leader_commit: 900,
+    };
+    let second = AppendEntriesPayload {
+        prev_log_index: 128,
+        prev_log_term: 1,
+        entries: (129..=256).map(|i| entry(1, i)).collect(),
+        ..first.clone()
+    };
+
+    assert!(follower.append_entries(first.clone()).success);
+    assert!(follower.append_entries(second).success);
+    assert_eq!(follower.commit_index, 256);
+    let applied: Vec<u64> = follower.take_committed().iter().map(|(i, _)| *i).collect();
+    assert_eq!(applied, (1..=256).collect::<Vec<u64>>());
+
+    // The first one again, retransmitted after a timeout and overtaken
+    assert!(follower.append_entries(first).success);
+    assert_eq!((follower.commit_index, follower.last_applied), (256, 256));
+    assert_eq!(follower.log.last_index(), 256);
+
+    // Nothing gets applied twice
+    let third = AppendEntriesPayload {
+        term: 1,
+        leader_id: "n0".to_owned(),
+        prev_log_index: 256,
+        prev_log_term: 1,
+        entries: vec![entry(1, 257)],
+        leader_commit: 900,
+    };
+    assert!(follower.append_entries(third).success);
+    let applied: Vec<u64> = follower.take_committed().iter().map(|(i, _)| *i).collect();
+    assert_eq!(applied, [257]);
+}
+
+#[test]
+fn leader_commits_once_a_majority_holds_an_entry() {
+    let mut state = leader("n0", &CLUSTER, "n1");
+    (0..3).for_each(|i| {
+        write(&mut state, i);
+    });
+    assert_eq!(state.commit_index, 0);
+
+    // (peer, match index, commit index)
+    let steps = [
+        ("n1", 1, 1),
+        ("n1", 2, 2),
+        // A late ack does not move anything back
+        ("n1", 1, 2),
+        ("n2", 3, 3),
+    ];
+    for (peer, match_index, commit_index) in steps {
+        ack(&mut state, peer, match_index);
+        assert_eq!(
+            state.commit_index,
+            commit_index,
+            "{:?}",
+            (peer, match_index)
+        );
+    }
+    let committed: Vec<u64> = state.take_committed().iter().map(|(i, _)| *i).collect();
+    assert_eq!(committed, [1, 2, 3]);
+}
+
+#[test]
+fn leader_commits_entries_of_previous_terms_only_with_its_own() {
+    let mut state = elected_with_log(2);
+    assert_eq!(state.current_term, 2);
+
+    // A majority holds them, but they could still be overwritten
+    ack(&mut state, "n1", 2);
+    assert_eq!(state.commit_index, 0);
+
+    // Committing an entry of the current term commits them too
+    let index = write(&mut state, 2);
+    ack(&mut state, "n1", index);
+    assert_eq!(state.commit_index, index);
+}
+
+#[test]
+fn next_index_backs_off_on_rejections() {
+    // (follower's retry hint, prev index sent next)
+    let steps = [(10, 3), (1, 1), (0, 0), (0, 0)];
+    let mut state = elected_with_log(4);
+    assert_eq!(append_for(&mut state, "n1").prev_log_index, 4);
+
+    for (hint, prev_log_index) in steps {
+        let reply = AppendEntriesOkPayload {
+            term: state.current_term,
+            success: false,
+            match_index: hint,
+        };
+        state.append_reply("n1", Some(reply));
+        // Never further than one entry back at a time, never before the
+        // first
+        assert_eq!(
+            append_for(&mut state, "n1").prev_log_index,
+            prev_log_index,
+            "hint {}",
+            hint
+        );
+    }
+}
+
+#[test]
+fn diverging_follower_converges_to_the_leader_log() {
+    // The follower holds entries of a term 1 leader, that n0 never got
+    let mut follower = node("n1", &CLUSTER);
+    follower.append_entries(AppendEntriesPayload {
+        term: 1,
+        leader_id: "n2".to_owned(),
+        prev_log_index: 0,
+        prev_log_term: 0,
+        entries: vec![entry(1, 0), entry(1, 1), entry(1, 2)],
+        leader_commit: 0,
+    });
+
+    let mut state = node("n0", &CLUSTER);
+    state.observe_term(1);
+    let req = state.become_candidate();
+    assert!(state.record_vote(req.term, "n2".to_owned(), true));
+    (10..15).for_each(|i| {
+        write(&mut state, i);
+    });
+
+    for _ in 0..10 {
+        let req = append_for(&mut state, "n1");
+        let reply = follower.append_entries(req);
+        state.append_reply("n1", Some(reply));
+        if terms(&follower.log) == terms(&state.log) {
+            break;
+        }
+    }
+    assert_eq!(follower.log.last_index(), 5);
+    for i in 1..=5 {
+        assert_eq!(follower.log.get(i), state.log.get(i), "entry {}", i);
+    }
+    assert_eq!(state.commit_index, 5);
+}
@@ -0,0 +1,267 @@
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use echo_server::raft::log::{LogEntry, Snapshot};
+use echo_server::raft::msg::{OpPayload, WritePayload};
+use echo_server::raft::node::Map;
+use echo_server::raft::state::RaftState;
+use echo_server::raft::storage::{crc32, FileStorage, Record, Storage};
+
+// Fresh directory per test, tests run in parallel. Removed once dropped.
+struct DataDir(PathBuf);
+
+impl Deref for DataDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn data_dir(name: &str) -> DataDir {
+    let dir = env::temp_dir().join(format!("raft_storage-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    DataDir(dir)
+}
+
+fn open(dir: &Path) -> FileStorage {
+    FileStorage::open(dir).expect("Cannot open the storage")
+}
+
+fn entry(index: u64, term: u64) -> Record {
+    Record::Entry {
+        index,
+        entry: LogEntry {
+            term,
+            op: OpPayload::Write(WritePayload::new(index as usize, index as usize)),
+        },
+    }
+}
+
+fn entries(range: std::ops::RangeInclusive<u64>) -> Vec<Record> {
+    range.map(|index| entry(index, 1)).collect()
+}
+
+fn json(records: &[Record]) -> Value {
+    serde_json::to_value(records).unwrap()
+}
+
+fn segments(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn load(dir: &Path) -> Vec<Record> {
+    open(dir).load().expect("Failed to load")
+}
+
+// Everything a restart must get back
+fn raft_state(state: &RaftState) -> (Value, Option<Snapshot>) {
+    let log: Vec<_> = (state.log.base_index() + 1..=state.log.last_index())
+        .map(|i| state.log.get(i).cloned())
+        .collect();
+    let summary = serde_json::json!({
+        "term": state.current_term,
+        "voted_for": state.voted_for,
+        "base_index": state.log.base_index(),
+        "log": log,
+        "commit_index": state.commit_index,
+        "last_applied": state.last_applied,
+    });
+    (summary, state.snapshot.clone())
+}
+
+fn restart(dir: &Path) -> RaftState {
+    RaftState::new("n0".to_owned(), HashSet::new(), Box::new(open(dir)))
+}
+
+fn copy_segments(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for path in segments(from) {
+        fs::copy(&path, to.join(path.file_name().unwrap())).unwrap();
+    }
+}
+
+#[test]
+fn crc32_matches_the_ieee_check_value() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn records_survive_a_restart_in_order() {
+    let dir = data_dir("restart");
+    let mut expected = vec![Record::HardState {
+        term: 3,
+        voted_for: Some("n1".to_owned()),
+    }];
+    expected.extend(entries(1..=5));
+    open(&dir).append(&expected).unwrap();
+    assert_eq!(json(&load(&dir)), json(&expected));
+
+    // Appends after a restart follow the previous ones
+    let mut storage = open(&dir);
+    storage.load().unwrap();
+    storage.append(&entries(6..=8)).unwrap();
+    expected.extend(entries(6..=8));
+    assert_eq!(json(&load(&dir)), json(&expected));
+}
+
+#[test]
+fn torn_final_record_is_cut_off() {
+    let dir = data_dir("torn");
+    let whole = entries(1..=3);
+    open(&dir).append(&whole).unwrap();
+    // Each run writes to a segment of its own, the last record alone in it
+    open(&dir).append(&entries(4..=4)).unwrap();
+    let last_len = fs::metadata(segments(&dir).pop().unwrap()).unwrap().len();
+
+    // Every cut inside the last record, header included
+    for cut in 1..last_len {
+        let dir = data_dir(&format!("torn-{}", cut));
+        open(&dir).append(&whole).unwrap();
+        open(&dir).append(&entries(4..=4)).unwrap();
+        let last = segments(&dir).pop().unwrap();
+        OpenOptions::new()
+            .write(true)
+            .open(&last)
+            .unwrap()
+            .set_len(cut)
+            .unwrap();
+
+        assert_eq!(json(&load(&dir)), json(&whole), "cut at {}", cut);
+        assert_eq!(fs::metadata(&last).unwrap().len(), 0, "cut at {}", cut);
+
+        // The storage goes on from the whole records
+        let mut storage = open(&dir);
+        storage.load().unwrap();
+        storage.append(&entries(4..=5)).unwrap();
+        assert_eq!(json(&load(&dir)), json(&entries(1..=5)), "cut at {}", cut);
+    }
+}
+
+#[test]
+fn corrupted_record_before_the_last_segment_fails_to_load() {
+    let dir = data_dir("corrupted");
+    let mut storage = open(&dir).with_segment_bytes(1);
+    storage.append(&entries(1..=2)).unwrap();
+    storage.append(&entries(3..=4)).unwrap();
+    let first = segments(&dir).remove(0);
+
+    let mut bytes = fs::read(&first).unwrap();
+    let last = bytes.len() - 2;
+    bytes[last] ^= 0xff;
+    fs::write(&first, bytes).unwrap();
+
+    let err = open(&dir).load().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn segments_roll_over_past_the_size_limit() {
+    let dir = data_dir("rollover");
+    let mut storage = open(&dir).with_segment_bytes(256);
+    let records = entries(1..=40);
+    records
+        .iter()
+        .for_each(|record| storage.append(std::slice::from_ref(record)).unwrap());
+
+    let paths = segments(&dir);
+    assert!(paths.len() > 1, "{} segments", paths.len());
+    // Only the last one may be below the limit
+    paths[..paths.len() - 1]
+        .iter()
+        .for_each(|path| assert!(fs::metadata(path).unwrap().len() >= 256));
+    assert_eq!(json(&load(&dir)), json(&records));
+}
+
+#[test]
+fn rewrite_replaces_every_segment() {
+    let dir = data_dir("rewrite");
+    let mut storage = open(&dir).with_segment_bytes(256);
+    storage.append(&entries(1..=40)).unwrap();
+    storage.append(&entries(41..=42)).unwrap();
+
+    storage.rewrite(&entries(41..=42)).unwrap();
+    assert_eq!(segments(&dir).len(), 1);
+    assert_eq!(json(&load(&dir)), json(&entries(41..=42)));
+}
+
+// A single node leader with applied entries, its state stored in `dir`
+fn leader_with_applied_entries(dir: &Path) -> (RaftState, Map) {
+    let mut state = restart(dir);
+    state.become_candidate();
+    assert!(state.try_become_leader());
+    let mut map = Map::new(HashMap::new());
+    for i in 0..10 {
+        let op = OpPayload::Write(WritePayload::new(i % 3, i));
+        state.propose(op).unwrap();
+    }
+    for (_, entry) in state.take_committed() {
+        if let OpPayload::Write(write) = entry.op {
+            map.apply_write(&write.key, &write.value).unwrap();
+        }
+    }
+    state.propose(OpPayload::Write(WritePayload::new(0, 100)));
+    (state, map)
+}
+
+#[test]
+fn crash_inside_rewrite_replays_to_the_same_state() {
+    let dir = data_dir("crash");
+    let (mut state, map) = leader_with_applied_entries(&dir);
+    let before = data_dir("crash-before");
+    copy_segments(&dir, &before);
+    let before_state = raft_state(&restart(&before));
+
+    state.compact(map);
+    let after_state = raft_state(&restart(&dir));
+    assert_eq!(after_state.1, state.snapshot);
+    assert_ne!(after_state, before_state);
+
+    // Crashed after the new segment was synced, before the old ones were
+    // removed: both are replayed
+    let both = data_dir("crash-both");
+    copy_segments(&before, &both);
+    copy_segments(&dir, &both);
+    assert!(segments(&both).len() > segments(&dir).len());
+    assert_eq!(raft_state(&restart(&both)), after_state);
+
+    // Crashed while writing the new segment: its records are either there
+    // as a whole or not at all
+    let new_segment = segments(&dir).pop().unwrap();
+    let new_len = fs::metadata(&new_segment).unwrap().len();
+    for cut in 0..new_len {
+        let torn = data_dir(&format!("crash-torn-{}", cut));
+        copy_segments(&both, &torn);
+        OpenOptions::new()
+            .write(true)
+            .open(segments(&torn).pop().unwrap())
+            .unwrap()
+            .set_len(cut)
+            .unwrap();
+
+        let restored = raft_state(&restart(&torn));
+        assert!(
+            restored == before_state || restored == after_state,
+            "cut at {}: {:?}",
+            cut,
+            restored
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+use echo_server::crdt::crdt::CrdtTrait;
+use echo_server::crdt::rga::{DeletePayload, InsertPayload, Rga, RgaData, RgaOp};
+
+const CASES: usize = 100;
+const NODES: [&str; 3] = ["n0", "n1", "n2"];
+
+fn replicas() -> Vec<Rga> {
+    NODES
+        .iter()
+        .map(|node| Rga::new(node, &HashSet::new()))
+        .collect()
+}
+
+fn insert(rga: &Rga, index: usize, value: Value) {
+    rga.apply(RgaOp::Insert(InsertPayload { index, value }))
+        .expect("RGA insert failed");
+}
+
+fn delete(rga: &Rga, index: usize) {
+    rga.apply(RgaOp::Delete(DeletePayload { index }))
+        .expect("RGA delete failed");
+}
+
+// Every delta sent so far, along with the node it came from
+fn flush(replicas: &[Rga], sent: &mut Vec<(usize, RgaData)>) {
+    replicas.iter().enumerate().for_each(|(i, r)| {
+        sent.extend(r.take_delta().map(|delta| (i, delta)));
+    });
+}
+
+fn deliver_all(replicas: &[Rga], sent: &[(usize, RgaData)]) {
+    replicas.iter().enumerate().for_each(|(i, r)| {
+        sent.iter()
+            .filter(|(from, _)| *from != i)
+            .for_each(|(_, delta)| r.merge_delta(delta.clone()));
+    });
+}
+
+#[test]
+fn replicas_converge_whatever_the_delivery_order() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..CASES {
+        let replicas = replicas();
+        let mut sent = vec![];
+        let mut inserted = HashSet::new();
+        let mut deleted = HashSet::new();
+
+        for step in 0..rng.gen_range(1..60) {
+            let i = rng.gen_range(0..NODES.len());
+            let values = replicas[i].read();
+            if values.is_empty() || rng.gen_bool(0.7) {
+                let value = json!(format!("{}-{}", NODES[i], step));
+                insert(&replicas[i], rng.gen_range(0..=values.len()), value.clone());
+                inserted.insert(value);
+            } else {
+                let index = rng.gen_range(0..values.len());
+                delete(&replicas[i], index);
+                deleted.insert(values[index].clone());
+            }
+            flush(&replicas, &mut sent);
+
+            // Some deltas arrive early, late, twice, or out of causal order
+            for _ in 0..rng.gen_range(0..3) {
+                if sent.is_empty() {
+                    break;
+                }
+                let (from, delta) = &sent[rng.gen_range(0..sent.len())];
+                let to = rng.gen_range(0..NODES.len());
+                if to != *from {
+                    replicas[to].merge_delta(delta.clone());
+                }
+            }
+        }
+        deliver_all(&replicas, &sent);
+
+        let expected: HashSet<Value> = inserted.difference(&deleted).cloned().collect();
+        let values = replicas[0].read();
+        assert_eq!(values.len(), expected.len());
+        assert_eq!(values.iter().cloned().collect::<HashSet<_>>(), expected);
+        replicas.iter().for_each(|r| {
+            assert_eq!(r.read(), values);
+            assert_eq!(r.data(), replicas[0].data());
+        });
+    }
+}
+
+#[test]
+fn concurrent_runs_do_not_interleave() {
+    let replicas = replicas();
+    let mut sent = vec![];
+    for (i, r) in replicas.iter().enumerate() {
+        (0..5).for_each(|k| insert(r, k, json!(format!("{}-{}", NODES[i], k))));
+    }
+    flush(&replicas, &mut sent);
+    deliver_all(&replicas, &sent);
+
+    let values = replicas[0].read();
+    assert_eq!(values.len(), 5 * NODES.len());
+    values.chunks(5).for_each(|run| {
+        let node = run[0].as_str().unwrap().split('-').next().unwrap();
+        let expected: Vec<Value> = (0..5).map(|k| json!(format!("{}-{}", node, k))).collect();
+        assert_eq!(run, expected.as_slice());
+    });
+    replicas.iter().for_each(|r| assert_eq!(r.read(), values));
+}
+
+#[test]
+fn inserts_after_a_concurrently_deleted_element_survive() {
+    let replicas = replicas();
+    let mut sent = vec![];
+    insert(&replicas[0], 0, json!("x"));
+    flush(&replicas, &mut sent);
+    deliver_all(&replicas, &sent);
+
+    // n0 deletes x while n1 appends after it
+    sent.clear();
+    delete(&replicas[0], 0);
+    insert(&replicas[1], 1, json!("y"));
+    flush(&replicas, &mut sent);
+    deliver_all(&replicas, &sent);
+
+    replicas
+        .iter()
+        .for_each(|r| assert_eq!(r.read(), vec![json!("y")]));
+}
+
+#[test]
+fn full_states_and_deltas_agree() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..CASES {
+        let replicas = replicas();
+        let mut sent = vec![];
+        for step in 0..rng.gen_range(1..30) {
+            let i = rng.gen_range(0..NODES.len());
+            let len = replicas[i].read().len();
+            if len == 0 || rng.gen_bool(0.7) {
+                insert(&replicas[i], rng.gen_range(0..=len), json!(step));
+            } else {
+                delete(&replicas[i], rng.gen_range(0..len));
+            }
+        }
+        flush(&replicas, &mut sent);
+
+        let by_state = Rga::new("c0", &HashSet::new());
+        replicas.iter().for_each(|r| by_state.merge(r.data()));
+        let by_delta = Rga::new("c0", &HashSet::new());
+        sent.iter()
+            .rev()
+            .for_each(|(_, delta)| by_delta.merge_delta(delta.clone()));
+
+        assert_eq!(by_state.data(), by_delta.data());
+        assert_eq!(by_state.read(), by_delta.read());
+    }
+}
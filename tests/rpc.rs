@@ -0,0 +1,160 @@
+use serde_json::{json, Value};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use echo_server::protocol::{Body, ErrorCode, Message};
+use echo_server::runtime::rpc::{RetryPolicy, RpcClient, RpcOptions, RpcResult, RpcStats};
+
+// Well past any deadline of these tests
+const PATIENCE: Duration = Duration::from_secs(5);
+
+// Every message the client wrote, with the time it did
+type Sent = Arc<Mutex<Vec<(Instant, Message<Value>)>>>;
+
+fn client() -> (RpcClient, Sent) {
+    let sent: Sent = Arc::new(Mutex::new(vec![]));
+    let sink_sent = Arc::clone(&sent);
+    let client = RpcClient::new(Arc::new(move |msg| {
+        sink_sent
+            .lock()
+            .unwrap()
+            .push((Instant::now(), msg.clone()));
+    }));
+    (client, sent)
+}
+
+fn request(msg_id: usize) -> Message<Value> {
+    let body = Body::new(json!({"type": "ping"}), Some(msg_id), None);
+    Message::new("n0".to_owned(), "n1".to_owned(), body)
+}
+
+fn reply(in_reply_to: usize, payload: Value) -> Message<Value> {
+    let body = Body::new(payload, Some(100), Some(in_reply_to));
+    Message::new("n1".to_owned(), "n0".to_owned(), body)
+}
+
+// Sends request `msg_id`, its result comes out of the receiver
+fn call(client: &RpcClient, msg_id: usize, opts: RpcOptions) -> Receiver<RpcResult> {
+    let (tx, rx) = mpsc::channel();
+    client.call(
+        request(msg_id),
+        opts,
+        Box::new(move |result| tx.send(result).unwrap()),
+    );
+    rx
+}
+
+fn stats(pending: usize, retries: usize, abandoned: usize) -> RpcStats {
+    RpcStats {
+        pending,
+        retries,
+        abandoned,
+    }
+}
+
+fn no_jitter(max_attempts: usize, max_timeout: Duration) -> RetryPolicy {
+    RetryPolicy {
+        jitter: 0.0,
+        ..RetryPolicy::exponential(Some(max_attempts), max_timeout)
+    }
+}
+
+#[test]
+fn reply_before_the_deadline_completes_the_call() {
+    let (client, sent) = client();
+    let rx = call(&client, 1, RpcOptions::new(PATIENCE));
+    assert_eq!(client.stats(), stats(1, 0, 0));
+    assert_eq!(sent.lock().unwrap().len(), 1);
+
+    assert!(client.resolve(reply(1, json!({"type": "pong"}))).is_none());
+    let msg = rx.recv_timeout(PATIENCE).unwrap().expect("Call failed");
+    assert_eq!(msg.body.payload["type"], "pong");
+    assert_eq!(client.stats(), stats(0, 0, 0));
+}
+
+#[test]
+fn error_reply_fails_the_call() {
+    let (client, _) = client();
+    let rx = call(&client, 1, RpcOptions::new(PATIENCE));
+
+    let error = json!({"type": "error", "code": 11, "text": "Try later"});
+    assert!(client.resolve(reply(1, error)).is_none());
+    let err = rx.recv_timeout(PATIENCE).unwrap().unwrap_err();
+    assert_eq!(err.code, ErrorCode::TemporarilyUnavailable);
+}
+
+#[test]
+fn replies_nobody_waits_for_come_back() {
+    let (client, _) = client();
+    let _rx = call(&client, 1, RpcOptions::new(PATIENCE));
+
+    let unknown = reply(2, json!({"type": "pong"}));
+    assert_eq!(client.resolve(unknown).unwrap().body.in_reply_to, Some(2));
+    let request = Message::new("c1".to_owned(), "n0".to_owned(), request(3).body);
+    assert!(client.resolve(request).is_some());
+    assert_eq!(client.stats(), stats(1, 0, 0));
+}
+
+#[test]
+fn unanswered_call_is_retried_then_abandoned() {
+    let (client, sent) = client();
+    let opts = RpcOptions::new(Duration::from_millis(10))
+        .with_retry(no_jitter(3, Duration::from_millis(40)));
+    let rx = call(&client, 1, opts);
+
+    let err = rx.recv_timeout(PATIENCE).unwrap().unwrap_err();
+    assert_eq!(err.code, ErrorCode::Timeout);
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 3);
+    assert!(sent.iter().all(|(_, msg)| msg.body.msg_id == Some(1)));
+    assert_eq!(client.stats(), stats(0, 2, 1));
+}
+
+#[test]
+fn retries_back_off_up_to_the_cap() {
+    let (client, sent) = client();
+    let opts = RpcOptions::new(Duration::from_millis(30))
+        .with_retry(no_jitter(4, Duration::from_millis(100)));
+    let rx = call(&client, 1, opts);
+    rx.recv_timeout(PATIENCE).unwrap().unwrap_err();
+
+    let sent = sent.lock().unwrap();
+    let gaps: Vec<Duration> = sent.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    // 30ms, doubled, then capped at 100ms instead of 120ms
+    for (gap, expected) in gaps.iter().zip([30, 60, 100]) {
+        let expected = Duration::from_millis(expected);
+        assert!(
+            *gap >= expected && *gap < expected * 2,
+            "{:?} for {:?}",
+            gaps,
+            expected
+        );
+    }
+}
+
+#[test]
+fn late_reply_after_expiry_comes_back_from_resolve() {
+    let (client, _) = client();
+    let rx = call(&client, 1, RpcOptions::new(Duration::from_millis(10)));
+    rx.recv_timeout(PATIENCE).unwrap().unwrap_err();
+    assert_eq!(client.stats(), stats(0, 0, 1));
+
+    let late = client.resolve(reply(1, json!({"type": "pong"})));
+    assert_eq!(late.unwrap().body.in_reply_to, Some(1));
+    // The callback ran once, for the timeout only
+    assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+}
+
+#[test]
+fn calls_time_out_independently() {
+    let (client, _) = client();
+    let short = call(&client, 1, RpcOptions::new(Duration::from_millis(10)));
+    let long = call(&client, 2, RpcOptions::new(PATIENCE));
+
+    short.recv_timeout(PATIENCE).unwrap().unwrap_err();
+    assert_eq!(client.stats(), stats(1, 0, 1));
+    assert!(client.resolve(reply(2, json!({"type": "pong"}))).is_none());
+    assert!(long.recv_timeout(PATIENCE).unwrap().is_ok());
+    assert_eq!(client.stats(), stats(0, 0, 1));
+}
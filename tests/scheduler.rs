@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use echo_server::runtime::scheduler::Scheduler;
+use echo_server::runtime::task::Task;
+
+// Runs of each task, by position
+#[derive(Debug, Default)]
+struct Runs([AtomicUsize; 2]);
+
+impl Runs {
+    fn get(&self, i: usize) -> usize {
+        self.0[i].load(Ordering::SeqCst)
+    }
+}
+
+fn counting(name: &str, i: usize) -> Task<Runs> {
+    Task::new(name, 5, move |runs: &Runs| {
+        runs.0[i].fetch_add(1, Ordering::SeqCst);
+    })
+}
+
+// Long enough for a few runs, and for a run in progress to complete
+fn a_few_runs() {
+    sleep(Duration::from_millis(60));
+}
+
+#[test]
+fn started_task_runs_until_stopped() {
+    let scheduler = Scheduler::default();
+    let runs = Arc::new(Runs::default());
+
+    scheduler.start(&runs, counting("count", 0));
+    assert!(scheduler.is_running("count"));
+    assert_eq!(scheduler.interval("count"), Some(Duration::from_millis(5)));
+    a_few_runs();
+    assert!(runs.get(0) > 1, "{} runs", runs.get(0));
+
+    assert!(scheduler.stop("count"));
+    assert!(!scheduler.is_running("count"));
+    assert_eq!(scheduler.interval("count"), None);
+    a_few_runs();
+    let stopped_at = runs.get(0);
+    a_few_runs();
+    assert_eq!(runs.get(0), stopped_at);
+
+    // Stopping twice, or a task never started, does nothing
+    assert!(!scheduler.stop("count"));
+    assert!(!scheduler.stop("other"));
+}
+
+#[test]
+fn stopping_one_task_leaves_the_others_running() {
+    let scheduler = Scheduler::default();
+    let runs = Arc::new(Runs::default());
+    scheduler.start(&runs, counting("first", 0));
+    scheduler.start(&runs, counting("second", 1));
+
+    scheduler.stop("first");
+    a_few_runs();
+    let (first, second) = (runs.get(0), runs.get(1));
+    a_few_runs();
+    assert_eq!(runs.get(0), first);
+    assert!(runs.get(1) > second);
+    assert!(scheduler.is_running("second"));
+    scheduler.stop_all();
+}
+
+#[test]
+fn starting_a_task_again_replaces_it() {
+    let scheduler = Scheduler::default();
+    let runs = Arc::new(Runs::default());
+    scheduler.start(&runs, counting("count", 0));
+    a_few_runs();
+
+    scheduler.start(&runs, counting("count", 1).with_jitter(0.5));
+    a_few_runs();
+    let (replaced, replacement) = (runs.get(0), runs.get(1));
+    assert!(replacement > 1, "{} runs", replacement);
+    a_few_runs();
+    assert_eq!(runs.get(0), replaced);
+    assert!(runs.get(1) > replacement);
+
+    scheduler.stop_all();
+    assert!(!scheduler.is_running("count"));
+}
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use echo_server::broadcast::topology::{Overlay, Topology};
+
+fn node_ids(count: usize) -> HashSet<String> {
+    (0..count).map(|i| format!("n{}", i)).collect()
+}
+
+// Every node's neighbors, as each node computes them for itself
+fn overlay(kind: Overlay, count: usize) -> HashMap<String, HashSet<String>> {
+    let nodes = node_ids(count);
+    nodes
+        .iter()
+        .map(|node| {
+            let neighbors = kind.neighbors(node, &nodes, &Topology::new());
+            (node.clone(), neighbors)
+        })
+        .collect()
+}
+
+fn reachable(graph: &HashMap<String, HashSet<String>>, from: &str) -> HashSet<String> {
+    let mut seen = HashSet::from([from.to_owned()]);
+    let mut queue = VecDeque::from([from.to_owned()]);
+    while let Some(node) = queue.pop_front() {
+        for n in &graph[&node] {
+            if seen.insert(n.clone()) {
+                queue.push_back(n.clone());
+            }
+        }
+    }
+    seen
+}
+
+fn edges(graph: &HashMap<String, HashSet<String>>) -> usize {
+    graph.values().map(HashSet::len).sum::<usize>() / 2
+}
+
+fn overlays() -> Vec<Overlay> {
+    let mut overlays = vec![Overlay::Star];
+    overlays.extend((1..=5).map(Overlay::KaryTree));
+    overlays.extend((2..=6).map(Overlay::RandomRegular));
+    overlays
+}
+
+#[test]
+fn overlays_are_connected_and_symmetric() {
+    for kind in overlays() {
+        for count in 1..=30 {
+            let graph = overlay(kind, count);
+            let label = (kind, count);
+            for (node, neighbors) in &graph {
+                assert!(!neighbors.contains(node), "{:?}: {} loops", label, node);
+                for n in neighbors {
+                    assert!(graph[n].contains(node), "{:?}: {} -> {}", label, node, n);
+                }
+            }
+            assert_eq!(reachable(&graph, "n0").len(), count, "{:?}", label);
+        }
+    }
+}
+
+#[test]
+fn trees_have_no_redundant_paths() {
+    for kind in [Overlay::Star, Overlay::KaryTree(1), Overlay::KaryTree(4)] {
+        for count in 1..=30 {
+            assert_eq!(
+                edges(&overlay(kind, count)),
+                count - 1,
+                "{:?}",
+                (kind, count)
+            );
+        }
+    }
+}
+
+#[test]
+fn random_overlay_degree_is_bounded_by_k() {
+    for k in 2..=6 {
+        for count in 1..=30 {
+            let graph = overlay(Overlay::RandomRegular(k), count);
+            let max = k.min(count - 1);
+            for (node, neighbors) in &graph {
+                assert!(
+                    neighbors.len() <= max && neighbors.len() + 1 >= max,
+                    "k {}, {} nodes: {} has {} neighbors",
+                    k,
+                    count,
+                    node,
+                    neighbors.len()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn overlays_parse_from_their_names() {
+    let cases = [
+        ("provided", Ok(Overlay::Provided)),
+        ("tree", Ok(Overlay::KaryTree(4))),
+        ("star", Ok(Overlay::Star)),
+        ("kary:1", Ok(Overlay::KaryTree(1))),
+        ("random:2", Ok(Overlay::RandomRegular(2))),
+        ("kary:0", Err(())),
+        // One neighbor each would only pair nodes up
+        ("random:1", Err(())),
+        ("random", Err(())),
+        ("ring", Err(())),
+    ];
+    for (name, expected) in cases {
+        assert_eq!(
+            name.parse::<Overlay>().map_err(|_| ()),
+            expected,
+            "{}",
+            name
+        );
+    }
+}
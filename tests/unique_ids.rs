@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+
+use echo_server::protocol::Message;
+use echo_server::unique_ids::generator::IdGenerator;
+use echo_server::unique_ids::msg::{GenerateOkPayload, ReqPayload, SendPayload};
+
+const NODES: usize = 25;
+const IDS_PER_NODE: usize = 2_000;
+
+fn generators(count: usize) -> Vec<IdGenerator> {
+    (0..count)
+        .map(|i| IdGenerator::new(format!("n{}", i)))
+        .collect()
+}
+
+#[test]
+fn ids_are_unique_across_nodes() {
+    let generators = generators(NODES);
+
+    let mut ids = HashSet::new();
+    for _ in 0..IDS_PER_NODE {
+        for generator in &generators {
+            assert!(ids.insert(generator.next_id()));
+        }
+    }
+    assert_eq!(ids.len(), NODES * IDS_PER_NODE);
+}
+
+#[test]
+fn ids_are_unique_with_concurrent_requests() {
+    // Several worker threads serve each node, all nodes run at once
+    let generators: Vec<Arc<IdGenerator>> = generators(NODES).into_iter().map(Arc::new).collect();
+
+    let handles: Vec<_> = generators
+        .iter()
+        .flat_map(|generator| (0..4).map(move |_| Arc::clone(generator)))
+        .map(|generator| {
+            thread::spawn(move || {
+                (0..IDS_PER_NODE / 4)
+                    .map(|_| generator.next_id())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let mut ids = HashSet::new();
+    for handle in handles {
+        for id in handle.join().unwrap() {
+            assert!(ids.insert(id.clone()), "duplicate id {}", id);
+        }
+    }
+    assert_eq!(ids.len(), NODES * IDS_PER_NODE);
+}
+
+#[test]
+fn ids_do_not_collide_on_prefix() {
+    // "n1" + "11" and "n11" + "1" must not produce the same id
+    let n1 = IdGenerator::new("n1".to_owned());
+    let n11 = IdGenerator::new("n11".to_owned());
+
+    let ids_n1: HashSet<_> = (0..20).map(|_| n1.next_id()).collect();
+    let ids_n11: HashSet<_> = (0..20).map(|_| n11.next_id()).collect();
+    assert!(ids_n1.is_disjoint(&ids_n11));
+}
+
+#[test]
+fn generate_round_trip() {
+    let input = r#"{"src":"c1","dest":"n1","body":{"type":"generate","msg_id":3}}"#;
+    let request: Message<ReqPayload> = serde_json::from_str(input).unwrap();
+    assert!(matches!(request.body.payload, ReqPayload::Generate));
+    assert_eq!(request.body.msg_id, Some(3));
+
+    let reply = SendPayload::GenerateOk(GenerateOkPayload::new("n1-0".to_owned()));
+    let value = serde_json::to_value(reply).unwrap();
+    assert_eq!(value["type"], "generate_ok");
+    assert_eq!(value["id"], "n1-0");
+}